@@ -15,8 +15,15 @@ pub const APP_BASE_ADDRESS: usize = 0x80400000;
 /// app memory region size
 pub const APP_SIZE_LIMIT: usize = 0x20000;
 
+/// 支持的最大核心数量, 与entry.asm中的启动栈数量一致
+pub const MAX_HART_NUM: usize = 8;
+
 /// max number of syscall
 pub const MAX_SYSCALL_NUM: usize = 500;
+
+/// dup3可以使用的最大描述符编号
+pub const MAX_FD_NUM: usize = 1024;
+
 /// freq of platform clock
 pub const CLOCK_FREQ: usize = 12500000;
 
@@ -39,5 +46,19 @@ pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 // TRAMPOLINE已经页对齐了
 pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
 
+/// 交换区在块设备上的起始块号, 位于easy-fs镜像之后
+pub const SWAP_BLOCK_START: usize = 16 * 2048;
+/// 交换区可以容纳的页面数量
+pub const SWAP_SLOT_NUM: usize = 2048;
+
+/// tmpfs没有指定size选项时可以使用的最大字节数
+pub const TMPFS_SIZE_LIMIT: usize = 16 * 1024 * 1024;
+
+/// 用户地址的上界, 即SV39地址空间的低半部分
+pub const USER_SPACE_END: usize = 1 << 38;
+
+/// 没有指定地址的mmap从这里开始查找空闲区域
+pub const MMAP_BASE: usize = 0x10_0000_0000;
+
 /// MMIO Map
 pub const MMIO: &[(usize, usize)] = &[(0x10001000, 0x1000)];
@@ -1,8 +1,12 @@
 //! Console by SBI Interface
 
 use crate::sbi::console_putchar;
+use crate::sync::SpinLock;
 use core::fmt::{self, Write};
 
+/// 多个核心同时输出时, 保证每次print的内容不会交错
+static PRINT_LOCK: SpinLock<()> = SpinLock::new(());
+
 struct Stdout;
 
 impl Write for Stdout {
@@ -15,6 +19,7 @@ impl Write for Stdout {
 }
 
 pub fn print(args: fmt::Arguments) {
+    let _guard = PRINT_LOCK.lock();
     Stdout.write_fmt(args).unwrap()
 }
 
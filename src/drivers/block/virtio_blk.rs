@@ -1,9 +1,8 @@
 use super::BlockDevice;
 use crate::mm::{
-    frame_alloc, frame_dealloc, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum,
-    StepByOne, VirtAddr,
+    frame_alloc_contiguous, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum, VirtAddr,
 };
-use crate::sync::UPSafeCell;
+use crate::sync::SpinLock;
 use alloc::vec::Vec;
 use lazy_static::*;
 use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
@@ -12,23 +11,27 @@ use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
 /// The Base Addr of control registers in Virtio_Block Device
 const VIRTIO0: usize = 0x10001000;
 /// VirtIOBlock Device driver structure for virtio_blk device
-pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static, VirtioHal>>);
+pub struct VirtIOBlock(SpinLock<VirtIOBlk<'static, VirtioHal>>);
+
+// VirtIOBlk中包含指向MMIO和DMA区域的指针, 由SpinLock保证互斥访问
+unsafe impl Send for VirtIOBlock {}
+unsafe impl Sync for VirtIOBlock {}
 
 lazy_static! {
-    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { UPSafeCell::new(Vec::new()) };
+    static ref QUEUE_FRAMES: SpinLock<Vec<FrameTracker>> = SpinLock::new(Vec::new());
 }
 
 impl BlockDevice for VirtIOBlock {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
         self.0
-            .exclusive_access()
+            .lock()
             .read_block(block_id, buf)
             .expect("Error When Reading VirtIOBlk");
     }
 
     fn write_block(&self, block_id: usize, buf: &[u8]) {
         self.0
-            .exclusive_access()
+            .lock()
             .write_block(block_id, buf)
             .expect("Error When Writing VirtIOBlk");
     }
@@ -39,7 +42,7 @@ impl VirtIOBlock {
     /// 在BaseAddr上创建一个VirtIOBlock Driver
     pub fn new() -> Self {
         unsafe {
-            Self(UPSafeCell::new(
+            Self(SpinLock::new(
                 VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
             ))
         }
@@ -50,29 +53,22 @@ pub struct VirtioHal;
 
 impl Hal for VirtioHal {
     fn dma_alloc(pages: usize) -> usize {
-        let mut ppn_base = PhysPageNum(0);
-        for i in 0..pages {
-            let frame = frame_alloc().unwrap();
-            if i == 0 {
-                ppn_base = frame.ppn;
-            }
-            assert_eq!(frame.ppn.0, ppn_base.0 + i);
-            QUEUE_FRAMES.exclusive_access().push(frame);
-        }
+        // 回收过的页面不一定连续, 需要一次分配连续的物理页面
+        let frames = frame_alloc_contiguous(pages).unwrap();
+        let pa: PhysAddr = frames[0].ppn.into();
+        QUEUE_FRAMES.lock().extend(frames);
 
-        let pa: PhysAddr = ppn_base.into();
         pa.0
     }
 
     fn dma_dealloc(pa: usize, pages: usize) -> i32 {
-        let pa = PhysAddr::from(pa);
+        let ppn_base: PhysPageNum = PhysAddr::from(pa).into();
+        let range = ppn_base.0..ppn_base.0 + pages;
 
-        let mut ppn_base: PhysPageNum = pa.into();
-
-        for _ in 0..pages {
-            frame_dealloc(ppn_base);
-            ppn_base.step();
-        }
+        // 由FrameTracker回收, 不能再调用frame_dealloc
+        QUEUE_FRAMES
+            .lock()
+            .retain(|frame| !range.contains(&frame.ppn.0));
         0
     }
 
@@ -1,12 +1,28 @@
   .section .text.entry
   .global _start
 _start:
+  # a0: hartid
+  # tp保存当前核心的hartid
+  mv tp, a0
+  # 每个核心使用独立的启动栈: boot_stack_top - hartid * 64K
   la sp, boot_stack_top
+  slli t0, a0, 16
+  sub sp, sp, t0
   call rust_main
 
+  # 其他核心由启动核心通过SBI HSM从这里启动
+  .global _start_secondary
+_start_secondary:
+  mv tp, a0
+  la sp, boot_stack_top
+  slli t0, a0, 16
+  sub sp, sp, t0
+  call rust_main_secondary
+
   .section .bss.stack
   .global boot_stack_lower_bound
 boot_stack_lower_bound:
-  .space 4096 * 16
+  # 4096 * 16 * MAX_HART_NUM
+  .space 4096 * 16 * 8
   .global boot_stack_top
 boot_stack_top:
@@ -0,0 +1,66 @@
+//! 与Linux一致的错误码, 系统调用失败时返回错误码的相反数
+
+/// 系统调用的错误码
+#[repr(isize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Errno {
+    /// 操作不允许, 例如文件系统不支持创建目录
+    EPERM = 1,
+    /// 没有这个文件或者目录
+    ENOENT = 2,
+    /// 没有这个进程
+    ESRCH = 3,
+    /// 阻塞被打断, 例如进程正在退出
+    EINTR = 4,
+    /// 无效的文件描述符
+    EBADF = 9,
+    /// 没有可以等待的子进程
+    ECHILD = 10,
+    /// 暂时不能完成, 例如futex的值已经改变
+    EAGAIN = 11,
+    /// 内存不足
+    ENOMEM = 12,
+    /// 没有权限
+    EACCES = 13,
+    /// 用户地址无效
+    EFAULT = 14,
+    /// 挂载点正在使用
+    EBUSY = 16,
+    /// 文件已经存在
+    EEXIST = 17,
+    /// 不能跨目录或者文件系统链接
+    EXDEV = 18,
+    /// 文件系统或者设备不支持该操作
+    ENODEV = 19,
+    /// 不是目录
+    ENOTDIR = 20,
+    /// 是目录
+    EISDIR = 21,
+    /// 无效的参数
+    EINVAL = 22,
+    /// 打开的文件描述符过多
+    EMFILE = 24,
+    /// 没有空间
+    ENOSPC = 28,
+    /// 不能定位, 例如管道和控制台
+    ESPIPE = 29,
+    /// 缓冲区太小
+    ERANGE = 34,
+    /// 会导致死锁, 例如等待自己
+    EDEADLK = 35,
+    /// 路径太长
+    ENAMETOOLONG = 36,
+    /// 不支持的系统调用
+    ENOSYS = 38,
+    /// 目录不为空
+    ENOTEMPTY = 39,
+    /// 等待超时
+    ETIMEDOUT = 110,
+}
+
+impl From<Errno> for isize {
+    /// 系统调用的返回值
+    fn from(errno: Errno) -> isize {
+        -(errno as isize)
+    }
+}
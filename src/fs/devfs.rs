@@ -0,0 +1,310 @@
+//! devfs: 设备文件, 每个设备打开时创建自己的File
+
+use super::stdio::{console_read, console_write};
+use super::vfs::{alloc_dev, makedev, FileSystem, Inode, InodeType, Metadata, SuperBlock};
+use super::{File, SeekFrom, Stat};
+use crate::config::SWAP_BLOCK_START;
+use crate::drivers::BLOCK_DEVICE;
+use crate::errno::Errno;
+use crate::mm::UserBuffer;
+use crate::sync::{Mutex, SpinLock};
+use crate::timer::get_time;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::BLOCK_SZ;
+
+/// /dev/vda的大小, 只包括文件系统所在的区域
+/// 之后的交换区中是其他进程被换出的页面, 不能被读取
+const VDA_SIZE: usize = SWAP_BLOCK_START * BLOCK_SZ;
+
+/// 设备的名字和Linux中的主次设备号, 按名字排序
+const DEVICES: [(&str, Device, (u64, u64)); 6] = [
+    ("console", Device::Console, (5, 1)),
+    ("null", Device::Null, (1, 3)),
+    ("random", Device::Random, (1, 8)),
+    ("urandom", Device::Random, (1, 9)),
+    ("vda", Device::Vda, (254, 0)),
+    ("zero", Device::Zero, (1, 5)),
+];
+
+/// devfs文件系统
+pub struct DevFileSystemType;
+
+/// 一个devfs实例
+struct DevSuperBlock {
+    dev: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Device {
+    /// 控制台, 即Stdin和Stdout
+    Console,
+    /// 丢弃写入, 读取时返回EOF
+    Null,
+    /// 丢弃写入, 读取时返回0
+    Zero,
+    /// 伪随机数, random和urandom都不会阻塞
+    Random,
+    /// 块设备上的文件系统区域, 读写不经过easy-fs的块缓存, 因此只能只读打开
+    Vda,
+}
+
+/// devfs中的inode, index为None时是根目录, 否则是DEVICES中的下标
+struct DevNode {
+    dev: u64,
+    index: Option<usize>,
+}
+
+/// 打开的设备
+struct DeviceFile {
+    device: Device,
+    readable: bool,
+    writable: bool,
+    /// 打开时设备inode的元数据, 用于stat
+    metadata: Metadata,
+    /// 块设备的读写位置
+    offset: Mutex<usize>,
+}
+
+impl FileSystem for DevFileSystemType {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    /// source和data被忽略
+    fn mount(&self, _source: &str, _data: &str) -> Option<Arc<dyn SuperBlock>> {
+        Some(Arc::new(DevSuperBlock { dev: alloc_dev() }))
+    }
+}
+
+impl SuperBlock for DevSuperBlock {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        Arc::new(DevNode {
+            dev: self.dev,
+            index: None,
+        })
+    }
+}
+
+impl Device {
+    fn inode_type(self) -> InodeType {
+        match self {
+            Device::Vda => InodeType::BlockDevice,
+            _ => InodeType::CharDevice,
+        }
+    }
+}
+
+impl DevNode {
+    /// 根目录为1, 设备从2开始
+    fn ino(&self) -> u64 {
+        self.index.map_or(1, |index| index as u64 + 2)
+    }
+}
+
+impl Inode for DevNode {
+    fn metadata(&self) -> Metadata {
+        let (type_, nlink, size, rdev) = match self.index {
+            None => (InodeType::Dir, 2, 0, 0),
+            Some(index) => {
+                let (_, device, (major, minor)) = DEVICES[index];
+                let size = if device == Device::Vda { VDA_SIZE } else { 0 };
+                (device.inode_type(), 1, size, makedev(major, minor))
+            }
+        };
+        Metadata {
+            dev: self.dev,
+            ino: self.ino(),
+            type_,
+            nlink,
+            size,
+            blksize: BLOCK_SZ as u32,
+            rdev,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+        }
+    }
+
+    /// 设备只能通过open_device读写
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        if self.index.is_some() {
+            return None;
+        }
+        let index = DEVICES.iter().position(|(device, _, _)| *device == name)?;
+        Some(Arc::new(DevNode {
+            dev: self.dev,
+            index: Some(index),
+        }))
+    }
+
+    fn list(&self) -> Vec<String> {
+        match self.index {
+            None => DEVICES
+                .iter()
+                .map(|(name, _, _)| String::from(*name))
+                .collect(),
+            Some(_) => Vec::new(),
+        }
+    }
+
+    fn open_device(
+        &self,
+        readable: bool,
+        writable: bool,
+    ) -> Option<Result<Arc<dyn File + Send + Sync>, Errno>> {
+        let device = DEVICES[self.index?].1;
+        if device == Device::Vda && writable {
+            return Some(Err(Errno::EACCES));
+        }
+        Some(Ok(Arc::new(DeviceFile {
+            device,
+            readable,
+            writable,
+            metadata: self.metadata(),
+            offset: Mutex::new(0),
+        })))
+    }
+}
+
+impl File for DeviceFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+
+    fn writable(&self) -> bool {
+        self.writable
+    }
+
+    fn read(&self, mut buf: UserBuffer) -> Result<usize, Errno> {
+        match self.device {
+            Device::Console => console_read(buf),
+            Device::Null => Ok(0),
+            Device::Zero => {
+                for slice in buf.buffers.iter_mut() {
+                    slice.fill(0);
+                }
+                Ok(buf.len())
+            }
+            Device::Random => {
+                let mut rng = RNG.lock();
+                for slice in buf.buffers.iter_mut() {
+                    slice.iter_mut().for_each(|byte| *byte = rng.next() as u8);
+                }
+                Ok(buf.len())
+            }
+            Device::Vda => {
+                let mut offset = self.offset.lock();
+                let read = read_vda_buffer(*offset, buf);
+                *offset += read;
+                Ok(read)
+            }
+        }
+    }
+
+    fn write(&self, buf: UserBuffer) -> Result<usize, Errno> {
+        match self.device {
+            Device::Console => Ok(console_write(buf)),
+            Device::Null | Device::Zero => Ok(buf.len()),
+            Device::Random => {
+                // 写入的数据混入随机数状态
+                let mut rng = RNG.lock();
+                for slice in buf.buffers.iter() {
+                    slice.iter().for_each(|byte| rng.mix(*byte));
+                }
+                Ok(buf.len())
+            }
+            // 只能只读打开
+            Device::Vda => Ok(0),
+        }
+    }
+
+    /// 只有块设备有读写位置, 其他设备和Linux一样总是返回0, 控制台不能定位
+    fn seek(&self, pos: SeekFrom) -> Option<usize> {
+        match self.device {
+            Device::Console => None,
+            Device::Vda => {
+                let mut offset = self.offset.lock();
+                *offset = pos.resolve(*offset, VDA_SIZE)?;
+                Some(*offset)
+            }
+            _ => Some(0),
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: UserBuffer) -> Option<usize> {
+        match self.device {
+            Device::Console => None,
+            Device::Vda => Some(read_vda_buffer(offset, buf)),
+            _ => self.read(buf).ok(),
+        }
+    }
+
+    fn write_at(&self, _offset: usize, buf: UserBuffer) -> Option<usize> {
+        match self.device {
+            Device::Console => None,
+            _ => self.write(buf).ok(),
+        }
+    }
+
+    fn get_stat(&self) -> Stat {
+        Stat::from(self.metadata)
+    }
+}
+
+/// 从块设备的offset处读取到用户缓冲区
+fn read_vda_buffer(offset: usize, mut buf: UserBuffer) -> usize {
+    let mut read = 0;
+    for slice in buf.buffers.iter_mut() {
+        let len = read_vda(offset + read, slice);
+        read += len;
+        if len < slice.len() {
+            break;
+        }
+    }
+    read
+}
+
+/// 从块设备的offset处读取, 超出设备末尾的部分不读
+fn read_vda(offset: usize, buf: &mut [u8]) -> usize {
+    let end = VDA_SIZE.min(offset.saturating_add(buf.len()));
+    let mut block = [0u8; BLOCK_SZ];
+    let mut pos = offset;
+    while pos < end {
+        let block_offset = pos % BLOCK_SZ;
+        let count = (BLOCK_SZ - block_offset).min(end - pos);
+        BLOCK_DEVICE.read_block(pos / BLOCK_SZ, &mut block);
+        buf[pos - offset..pos - offset + count]
+            .copy_from_slice(&block[block_offset..block_offset + count]);
+        pos += count;
+    }
+    end.saturating_sub(offset)
+}
+
+/// xorshift64*伪随机数生成器, 第一次使用时用时钟初始化
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        if self.state == 0 {
+            self.state = get_time() as u64 | 1;
+        }
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn mix(&mut self, byte: u8) {
+        self.state = (self.state ^ byte as u64).rotate_left(8);
+    }
+}
+
+static RNG: SpinLock<Rng> = SpinLock::new(Rng { state: 0 });
@@ -0,0 +1,130 @@
+//! easy-fs: 块设备上的easy-fs适配到VFS
+
+use super::vfs::{alloc_dev, FileSystem, Inode, InodeType, Metadata, SuperBlock};
+use crate::drivers::BLOCK_DEVICE;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{EasyFileSystem, BLOCK_SZ};
+use lazy_static::*;
+
+/// easy-fs文件系统, 只能挂载唯一的块设备
+pub struct EasyFileSystemType;
+
+/// 块设备上的easy-fs实例
+struct EasyFsSuperBlock {
+    dev: u64,
+    root: Arc<easy_fs::Inode>,
+}
+
+/// easy-fs中的inode
+struct EasyFsInode {
+    inode: Arc<easy_fs::Inode>,
+    sb: Arc<EasyFsSuperBlock>,
+}
+
+lazy_static! {
+    /// 同一个块设备只能打开一次, 多次挂载共享同一个实例
+    static ref EASY_FS: Arc<EasyFsSuperBlock> = {
+        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
+        Arc::new(EasyFsSuperBlock {
+            dev: alloc_dev(),
+            root: Arc::new(EasyFileSystem::root_inode(&efs)),
+        })
+    };
+}
+
+impl FileSystem for EasyFileSystemType {
+    fn name(&self) -> &'static str {
+        "easyfs"
+    }
+
+    /// source和data被忽略, 总是挂载BLOCK_DEVICE
+    fn mount(&self, _source: &str, _data: &str) -> Option<Arc<dyn SuperBlock>> {
+        Some(EASY_FS.clone())
+    }
+}
+
+impl SuperBlock for EasyFsSuperBlock {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        Arc::new(EasyFsInode {
+            inode: self.root.clone(),
+            sb: EASY_FS.clone(),
+        })
+    }
+}
+
+impl EasyFsInode {
+    fn wrap(&self, inode: Arc<easy_fs::Inode>) -> Arc<dyn Inode> {
+        Arc::new(EasyFsInode {
+            inode,
+            sb: self.sb.clone(),
+        })
+    }
+}
+
+impl Inode for EasyFsInode {
+    fn metadata(&self) -> Metadata {
+        let (block_id, block_offset) = self.inode.get_block_metadata();
+        Metadata {
+            dev: self.sb.dev,
+            ino: self
+                .sb
+                .root
+                .find_inode_id_by_block(block_id as u32, block_offset)
+                .unwrap_or(0) as u64,
+            type_: match self.inode.find_file_type() {
+                easy_fs::InodeType::FILE => InodeType::File,
+                easy_fs::InodeType::DIR => InodeType::Dir,
+            },
+            nlink: self.inode.get_nlink(),
+            size: self.inode.get_size() as usize,
+            blksize: BLOCK_SZ as u32,
+            rdev: 0,
+            // easy-fs不记录时间
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.inode.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        self.inode.write_at(offset, buf)
+    }
+
+    fn clear(&self) {
+        self.inode.clear();
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        self.inode.find(name).map(|inode| self.wrap(inode))
+    }
+
+    /// easy-fs只有创建普通文件的接口, 创建目录和设备文件时返回None
+    fn create(&self, name: &str, type_: InodeType) -> Option<Arc<dyn Inode>> {
+        match type_ {
+            InodeType::File => self.inode.create(name).map(|inode| self.wrap(inode)),
+            _ => None,
+        }
+    }
+
+    fn link(&self, old_name: &str, new_name: &str) -> bool {
+        self.inode.linkat(old_name, new_name) == 0
+    }
+
+    fn unlink(&self, name: &str) -> bool {
+        self.inode.unlinkat(name) == 0
+    }
+
+    fn list(&self) -> Vec<String> {
+        self.inode.ls()
+    }
+
+    fn is_dir(&self) -> bool {
+        self.inode.find_file_type() == easy_fs::InodeType::DIR
+    }
+}
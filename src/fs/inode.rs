@@ -1,40 +1,47 @@
 //! File INode
 
-use super::{File, Stat, StatMode};
-use crate::drivers::BLOCK_DEVICE;
+use super::path::{join_path, split_parent};
+use super::vfs::{self, Inode, InodeType};
+use super::{DirEntry, File, SeekFrom, Stat};
+use crate::errno::Errno;
 use crate::mm::UserBuffer;
-use crate::sync::UPSafeCell;
+use crate::sync::Mutex;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
-use easy_fs::{EasyFileSystem, Inode, InodeType};
-use lazy_static::*;
 
 /// OS看见的Inode接口，在内存中
 pub struct OSInode {
     readable: bool,
     writable: bool,
-    inner: UPSafeCell<OSInodeInner>,
+    /// 打开时的绝对路径
+    path: String,
+    /// 读写文件需要访问块设备, 持有时间较长, 因此使用可睡眠的锁
+    inner: Mutex<OSInodeInner>,
 }
 
 /// OSINode的可变部分
 pub struct OSInodeInner {
+    /// 文件的读写位置, 目录中下一个目录项的序号
     offset: usize,
-    inode: Arc<Inode>,
+    inode: Arc<dyn Inode>,
 }
 
 impl OSInode {
     /// 根据Inode和Flag生成一个OSInode
-    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
+    pub fn new(readable: bool, writable: bool, inode: Arc<dyn Inode>, path: String) -> Self {
         Self {
             readable,
             writable,
-            inner: unsafe { UPSafeCell::new(OSInodeInner { offset: 0, inode }) },
+            path,
+            inner: Mutex::new(OSInodeInner { offset: 0, inode }),
         }
     }
 
     /// 从这个Inode中读取全部
     pub fn read_all(&self) -> Vec<u8> {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner.lock();
         let mut buffer = [0u8; 512];
         let mut v: Vec<u8> = Vec::new();
 
@@ -52,37 +59,26 @@ impl OSInode {
 
     /// 获取偏移
     pub fn get_offset(&self) -> usize {
-        let inner = self.inner.exclusive_access();
+        let inner = self.inner.lock();
         inner.offset
     }
 
     /// Dump metadata
     pub fn dump_metadata(&self) {
-        let inner = self.inner.exclusive_access();
-        let (block_id, block_offset) = inner.inode.get_block_metadata();
+        let inner = self.inner.lock();
+        let metadata = inner.inode.metadata();
 
         println!(
-            "[Kernel] OSInode: size = {} block id {}, block offset {}, offset {}",
-            inner.inode.get_size(),
-            block_id,
-            block_offset,
-            inner.offset
+            "[Kernel] OSInode: size = {} dev {}, ino {}, offset {}",
+            metadata.size, metadata.dev, metadata.ino, inner.offset
         );
     }
 }
 
-lazy_static! {
-    /// 根目录
-    pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
-        Arc::new(EasyFileSystem::root_inode(&efs))
-    };
-}
-
 /// 列出所有APP
 pub fn list_apps() {
     println!("/**** APPS ****/");
-    for app in ROOT_INODE.ls() {
+    for app in vfs::lookup("/").unwrap().list() {
         println!("{}", app);
     }
     println!("/**************/");
@@ -101,15 +97,18 @@ bitflags! {
         const CREATE = 1 << 9;
         /// 清空文件
         const TRUNC = 1 << 10;
+        /// exec时关闭
+        const CLOEXEC = 1 << 19;
     }
 }
 
 impl OpenFlags {
-    /// 获取文件的读写权限, 读写权限目前一定合法
+    /// 获取文件的读写权限, 只由访问模式WRONLY和RDWR决定, 不受CREATE和CLOEXEC等影响
     pub fn read_write(&self) -> (bool, bool) {
-        if self.is_empty() {
+        let mode = *self & (Self::WRONLY | Self::RDWR);
+        if mode.is_empty() {
             (true, false)
-        } else if self.contains(Self::WRONLY) {
+        } else if mode == Self::WRONLY {
             (false, true)
         } else {
             (true, true)
@@ -117,29 +116,165 @@ impl OpenFlags {
     }
 }
 
-/// 打开一个文件
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+/// 查找规范路径的父目录, 返回父目录和最后一部分
+fn lookup_parent(path: &str) -> Result<(Arc<dyn Inode>, &str), Errno> {
+    let (parent, name) = split_parent(path).ok_or(Errno::EINVAL)?;
+    Ok((lookup_dir(parent)?, name))
+}
+
+/// 查找规范路径处的目录
+fn lookup_dir(path: &str) -> Result<Arc<dyn Inode>, Errno> {
+    match vfs::lookup(path) {
+        Some(dir) if dir.is_dir() => Ok(dir),
+        Some(_) => Err(Errno::ENOTDIR),
+        None => Err(Errno::ENOENT),
+    }
+}
+
+/// 打开path处的文件, 设备文件使用设备自己的File实现
+pub fn open(path: &str, flags: OpenFlags) -> Result<Arc<dyn File + Send + Sync>, Errno> {
+    let (readable, writable) = flags.read_write();
+    if let Some(inode) = vfs::lookup(path) {
+        if let Some(file) = inode.open_device(readable, writable) {
+            return file;
+        }
+    }
+    open_file(path, flags).map(|file| file as Arc<dyn File + Send + Sync>)
+}
+
+/// 打开一个普通文件或者目录, path是规范的绝对路径
+/// 目录只能以只读方式打开
+pub fn open_file(path: &str, flags: OpenFlags) -> Result<Arc<OSInode>, Errno> {
     let (readable, writable) = flags.read_write();
 
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
+    // 挂载点可能遮住了父目录中的同名文件, 需要先按完整路径查找
+    let inode = if let Some(inode) = vfs::lookup(path) {
+        let type_ = inode.metadata().type_;
+        if matches!(type_, InodeType::CharDevice | InodeType::BlockDevice) {
+            return Err(Errno::ENODEV);
+        }
+        if type_ == InodeType::Dir {
+            if writable || flags.contains(OpenFlags::CREATE) {
+                return Err(Errno::EISDIR);
+            }
+        } else if flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC) {
             inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
-        } else {
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
         }
+        inode
+    } else if flags.contains(OpenFlags::CREATE) {
+        let (parent, name) = lookup_parent(path)?;
+        parent.create(name, InodeType::File).ok_or(Errno::ENOSPC)?
     } else {
-        ROOT_INODE.find(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
-            }
-            Arc::new(OSInode::new(readable, writable, inode))
-        })
+        return Err(Errno::ENOENT);
+    };
+
+    Ok(Arc::new(OSInode::new(
+        readable,
+        writable,
+        inode,
+        String::from(path),
+    )))
+}
+
+/// 在path处创建目录, 文件系统不支持目录时返回EPERM
+pub fn make_dir(path: &str) -> Result<(), Errno> {
+    if vfs::lookup(path).is_some() {
+        return Err(Errno::EEXIST);
+    }
+    let (parent, name) = lookup_parent(path)?;
+    parent
+        .create(name, InodeType::Dir)
+        .map(|_| ())
+        .ok_or(Errno::EPERM)
+}
+
+/// 删除path处的目录项, 目录只有为空时才能删除
+/// expect_dir表示调用者期望删除目录, 与实际类型不符时失败
+/// 挂载点, 以及包含挂载点的目录不能删除
+pub fn unlink(path: &str, expect_dir: bool) -> Result<(), Errno> {
+    if vfs::is_mount_point(path) || !vfs::mount_points_in(path).is_empty() {
+        return Err(Errno::EBUSY);
+    }
+    let (parent, name) = lookup_parent(path)?;
+    let inode = parent.lookup(name).ok_or(Errno::ENOENT)?;
+    match (inode.is_dir(), expect_dir) {
+        (true, false) => return Err(Errno::EISDIR),
+        (false, true) => return Err(Errno::ENOTDIR),
+        (true, true) if !inode.list().is_empty() => return Err(Errno::ENOTEMPTY),
+        _ => {}
+    }
+    if parent.unlink(name) {
+        Ok(())
+    } else {
+        Err(Errno::EPERM)
     }
 }
 
+/// 为old_path创建硬链接new_path
+/// 只能在同一个目录中创建链接, 并且不能链接目录和挂载点
+pub fn link(old_path: &str, new_path: &str) -> Result<(), Errno> {
+    let (old_parent, old_name) = split_parent(old_path).ok_or(Errno::EPERM)?;
+    let (new_parent, new_name) = split_parent(new_path).ok_or(Errno::EEXIST)?;
+    if old_parent != new_parent {
+        return Err(Errno::EXDEV);
+    }
+
+    let parent = lookup_dir(old_parent)?;
+    let inode = parent.lookup(old_name).ok_or(Errno::ENOENT)?;
+    if inode.is_dir() {
+        return Err(Errno::EPERM);
+    }
+    if old_name == new_name || vfs::is_mount_point(new_path) || parent.lookup(new_name).is_some() {
+        return Err(Errno::EEXIST);
+    }
+    if parent.link(old_name, new_name) {
+        Ok(())
+    } else {
+        Err(Errno::EPERM)
+    }
+}
+
+/// 不打开文件, 直接获取path处的Stat
+pub fn stat(path: &str) -> Option<Stat> {
+    vfs::lookup(path).map(|inode| Stat::from(inode.metadata()))
+}
+
+/// path是否是目录
+pub fn is_dir_path(path: &str) -> bool {
+    vfs::lookup(path).map_or(false, |inode| inode.is_dir())
+}
+
+/// 从offset处读取到用户缓冲区, 返回读到的字节数
+fn read_inode(inode: &Arc<dyn Inode>, offset: usize, mut buf: UserBuffer) -> usize {
+    let mut total_read_size = 0usize;
+
+    for slice in buf.buffers.iter_mut() {
+        let read_size = inode.read_at(offset + total_read_size, *slice);
+        total_read_size += read_size;
+        if read_size < slice.len() {
+            break;
+        }
+    }
+
+    total_read_size
+}
+
+/// 将用户缓冲区写入offset处, 返回写入的字节数
+fn write_inode(inode: &Arc<dyn Inode>, offset: usize, buf: UserBuffer) -> usize {
+    let mut total_write_size = 0usize;
+
+    for slice in buf.buffers.iter() {
+        let write_size = inode.write_at(offset + total_write_size, *slice);
+        total_write_size += write_size;
+        // 磁盘或者tmpfs空间不足时只能写入一部分
+        if write_size < slice.len() {
+            break;
+        }
+    }
+
+    total_write_size
+}
+
 /// OSInode需要实现File Trait
 impl File for OSInode {
     fn readable(&self) -> bool {
@@ -150,57 +285,104 @@ impl File for OSInode {
         self.writable
     }
 
-    fn read(&self, mut buf: UserBuffer) -> usize {
-        let mut inner = self.inner.exclusive_access();
-        let mut total_read_size = 0usize;
-
-        for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
-            if read_size == 0 {
-                break;
-            }
-            inner.offset += read_size;
-            total_read_size += read_size;
-        }
+    fn read(&self, buf: UserBuffer) -> Result<usize, Errno> {
+        let mut inner = self.inner.lock();
+        let read_size = read_inode(&inner.inode, inner.offset, buf);
+        inner.offset += read_size;
+        Ok(read_size)
+    }
 
-        total_read_size
+    fn write(&self, buf: UserBuffer) -> Result<usize, Errno> {
+        let mut inner = self.inner.lock();
+        let write_size = write_inode(&inner.inode, inner.offset, buf);
+        inner.offset += write_size;
+        Ok(write_size)
     }
 
-    fn write(&self, buf: UserBuffer) -> usize {
-        let mut inner = self.inner.exclusive_access();
-        let mut total_write_size = 0usize;
+    /// 目录的读写位置是下一个目录项的序号
+    fn seek(&self, pos: SeekFrom) -> Option<usize> {
+        let mut inner = self.inner.lock();
+        let size = inner.inode.metadata().size;
+        inner.offset = pos.resolve(inner.offset, size)?;
+        Some(inner.offset)
+    }
 
-        for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
-            inner.offset += write_size;
-            total_write_size += write_size;
-        }
+    fn read_at(&self, offset: usize, buf: UserBuffer) -> Option<usize> {
+        let inner = self.inner.lock();
+        Some(read_inode(&inner.inode, offset, buf))
+    }
 
-        total_write_size
+    fn write_at(&self, offset: usize, buf: UserBuffer) -> Option<usize> {
+        let inner = self.inner.lock();
+        Some(write_inode(&inner.inode, offset, buf))
     }
 
     fn get_stat(&self) -> Stat {
-        let inner = self.inner.exclusive_access();
-
-        let nlink = inner.inode.get_nlink();
-        let (blk_id, blk_offset) = inner.inode.get_block_metadata();
-        let ino = ROOT_INODE
-            .find_inode_id_by_block(blk_id as u32, blk_offset)
-            .unwrap();
-        let file_type = inner.inode.find_file_type();
-
-        let stat = Stat {
-            dev: 0u64,
-            ino: ino as u64,
-            mode: match file_type {
-                InodeType::FILE => StatMode::FILE,
-                InodeType::DIR => StatMode::DIR,
-            },
-            nlink: nlink,
-            pad: [0u64; 7],
-        };
-
-        stat
+        Stat::from(self.inner.lock().inode.metadata())
+    }
+
+    fn inode(&self) -> Option<Arc<dyn Inode>> {
+        Some(self.inner.lock().inode.clone())
+    }
+
+    fn path(&self) -> Option<String> {
+        Some(self.path.clone())
+    }
+
+    fn read_dir(&self, max_bytes: usize) -> Result<Vec<DirEntry>, Errno> {
+        let mut inner = self.inner.lock();
+        if !inner.inode.is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+
+        let parent_path = split_parent(&self.path).map_or("/", |(parent, _)| parent);
+        let mut names = vec![String::from("."), String::from("..")];
+        names.extend(inner.inode.list());
+        // 挂载点不一定在下层文件系统中存在
+        for name in vfs::mount_points_in(&self.path) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        let mut entries = Vec::new();
+        let mut total = 0;
+        let mut full = false;
+        for name in names.into_iter().skip(inner.offset) {
+            let inode = match name.as_str() {
+                "." => Some(inner.inode.clone()),
+                ".." => vfs::lookup(parent_path),
+                // 按完整路径查找, 被挂载点遮住的目录项显示挂载的文件系统
+                _ => vfs::lookup(&join_path(&self.path, &name)),
+            };
+            // 目录项可能已经被删除
+            let inode = match inode {
+                Some(inode) => inode,
+                None => {
+                    inner.offset += 1;
+                    continue;
+                }
+            };
+            let metadata = inode.metadata();
+            let entry = DirEntry {
+                ino: metadata.ino,
+                off: inner.offset as u64 + 1,
+                mode: metadata.mode(),
+                name,
+            };
+            if total + entry.dirent64_len() > max_bytes {
+                full = true;
+                break;
+            }
+            total += entry.dirent64_len();
+            inner.offset += 1;
+            entries.push(entry);
+        }
+
+        // 缓冲区放不下下一项
+        if entries.is_empty() && full {
+            return Err(Errno::EINVAL);
+        }
+        Ok(entries)
     }
 }
@@ -1,11 +1,37 @@
 //! File Trade and inode
+mod devfs;
+mod easyfs;
 mod inode;
+mod path;
+mod pipe;
+mod procfs;
 mod stdio;
+mod tmpfs;
+mod vfs;
 
+use crate::errno::Errno;
 use crate::mm::UserBuffer;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 
-pub use inode::{list_apps, open_file, OSInode, OpenFlags, ROOT_INODE};
-pub use stdio::{Stdin, Stdout};
+pub use inode::{
+    is_dir_path, link, list_apps, make_dir, open, open_file, stat, unlink, OSInode, OpenFlags,
+};
+pub use path::absolute_path;
+pub use pipe::{make_pipe, Pipe};
+pub use stdio::{poll_stdin, Stdin, Stdout};
+pub use vfs::{
+    alloc_dev, mount, register_filesystem, umount, FileSystem, Inode, InodeType, Metadata,
+    SuperBlock,
+};
+
+/// 挂载内核启动时就需要的文件系统
+pub fn init() {
+    mount("", "/tmp", "tmpfs", "").unwrap();
+    mount("", "/proc", "proc", "").unwrap();
+    mount("", "/dev", "devfs", "").unwrap();
+}
 
 /// trait FIle for all file types
 pub trait File: Send + Sync {
@@ -13,28 +39,192 @@ pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     /// 判断是否可写
     fn writable(&self) -> bool;
-    /// 读取数据写入缓冲区，返回读到的字节数
-    fn read(&self, buf: UserBuffer) -> usize;
-    /// 从缓冲区写入数据，返回成功写入的字节数
-    fn write(&self, buf: UserBuffer) -> usize;
+    /// 读取数据写入缓冲区，返回读到的字节数, 等待数据时被信号打断返回EINTR
+    fn read(&self, buf: UserBuffer) -> Result<usize, Errno>;
+    /// 从缓冲区写入数据，返回成功写入的字节数, 没有写入任何数据时被信号打断返回EINTR
+    fn write(&self, buf: UserBuffer) -> Result<usize, Errno>;
     /// Stat
     fn get_stat(&self) -> Stat;
+    /// 移动读写位置, 返回新的位置
+    /// 管道等不能定位的文件, 或者新的位置不合法时返回None
+    fn seek(&self, _pos: SeekFrom) -> Option<usize> {
+        None
+    }
+    /// 从offset处读取, 不改变读写位置, 不能定位的文件返回None
+    fn read_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> {
+        None
+    }
+    /// 写入offset处, 不改变读写位置, 不能定位的文件返回None
+    fn write_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> {
+        None
+    }
+    /// 用于mmap的inode, 不能被映射的文件返回None
+    fn inode(&self) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    /// 打开时的绝对路径, 用于以该目录为基准解析相对路径
+    fn path(&self) -> Option<String> {
+        None
+    }
+    /// 从当前位置读取目录项, 总长度不超过max_bytes, 已经读完时返回空Vec
+    /// 不是目录时返回ENOTDIR, 缓冲区放不下下一项时返回EINVAL
+    fn read_dir(&self, _max_bytes: usize) -> Result<Vec<DirEntry>, Errno> {
+        Err(Errno::ENOTDIR)
+    }
+}
+
+/// lseek的基准位置
+#[derive(Clone, Copy, Debug)]
+pub enum SeekFrom {
+    /// 文件开头
+    Start(usize),
+    /// 当前位置
+    Current(isize),
+    /// 文件末尾
+    End(isize),
+}
+
+impl SeekFrom {
+    /// 根据当前位置和文件大小计算新的位置, 结果为负数或者溢出时返回None
+    pub fn resolve(self, current: usize, size: usize) -> Option<usize> {
+        match self {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => current.checked_add_signed(offset),
+            SeekFrom::End(offset) => size.checked_add_signed(offset),
+        }
+    }
+}
+
+/// 目录项
+pub struct DirEntry {
+    /// inode编号
+    pub ino: u64,
+    /// 下一项的位置, 即读到这一项之后seek所用的偏移
+    pub off: u64,
+    /// 文件类型
+    pub mode: StatMode,
+    /// 文件名
+    pub name: String,
+}
+
+impl DirEntry {
+    /// 写入linux_dirent64时占用的字节数: 19字节的头部, 文件名和结尾的0, 按8字节对齐
+    pub fn dirent64_len(&self) -> usize {
+        (19 + self.name.len() + 1 + 7) & !7
+    }
+}
+
+/// 文件描述符表中的一项
+#[derive(Clone)]
+pub struct FileDescriptor {
+    /// 打开的文件, dup得到的描述符共享同一个文件
+    pub file: Arc<dyn File + Send + Sync>,
+    /// exec时关闭该描述符
+    pub cloexec: bool,
+}
+
+impl FileDescriptor {
+    /// 创建一个文件描述符
+    pub fn new(file: Arc<dyn File + Send + Sync>, cloexec: bool) -> Self {
+        Self { file, cloexec }
+    }
 }
 
 #[repr(C)]
-#[derive(Debug)]
-/// The Stat of a Inode
+#[derive(Debug, Clone, Copy)]
+/// The Stat of a Inode, 与riscv64 Linux的struct stat布局一致
 pub struct Stat {
-    /// 文件所在磁盘驱动器号，rcore中默认为0
+    /// 文件所在文件系统的设备号, 每次挂载时分配
     pub dev: u64,
     /// inode文件所在inode编号
     pub ino: u64,
-    /// 文件类型
-    pub mode: StatMode,
+    /// 文件类型和权限
+    pub mode: u32,
     /// 硬链接数量，初始为1
     pub nlink: u32,
-    /// unused pad
-    pad: [u64; 7],
+    /// 所有者, 没有用户时总是0
+    pub uid: u32,
+    /// 所属组, 没有用户时总是0
+    pub gid: u32,
+    /// 设备文件的设备号
+    pub rdev: u64,
+    __pad1: u64,
+    /// 文件大小
+    pub size: i64,
+    /// 文件系统的块大小
+    pub blksize: i32,
+    __pad2: i32,
+    /// 占用的512字节块数量
+    pub blocks: i64,
+    /// 最后访问时间
+    pub atime_sec: i64,
+    /// 最后访问时间的纳秒部分
+    pub atime_nsec: i64,
+    /// 最后修改内容的时间
+    pub mtime_sec: i64,
+    /// 最后修改内容的时间的纳秒部分
+    pub mtime_nsec: i64,
+    /// 最后修改元数据的时间
+    pub ctime_sec: i64,
+    /// 最后修改元数据的时间的纳秒部分
+    pub ctime_nsec: i64,
+    __unused: [u32; 2],
+}
+
+impl Stat {
+    /// 只有文件类型的Stat, 用于没有inode的文件, 其余字段为0
+    pub fn new(mode: StatMode) -> Self {
+        Self {
+            dev: 0,
+            ino: 0,
+            mode: mode.bits() | mode.default_permission(),
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            __pad1: 0,
+            size: 0,
+            blksize: 0,
+            __pad2: 0,
+            blocks: 0,
+            atime_sec: 0,
+            atime_nsec: 0,
+            mtime_sec: 0,
+            mtime_nsec: 0,
+            ctime_sec: 0,
+            ctime_nsec: 0,
+            __unused: [0; 2],
+        }
+    }
+}
+
+impl From<Metadata> for Stat {
+    fn from(metadata: Metadata) -> Self {
+        let (atime_sec, atime_nsec) = split_ns(metadata.atime);
+        let (mtime_sec, mtime_nsec) = split_ns(metadata.mtime);
+        let (ctime_sec, ctime_nsec) = split_ns(metadata.ctime);
+        Self {
+            dev: metadata.dev,
+            ino: metadata.ino,
+            nlink: metadata.nlink,
+            rdev: metadata.rdev,
+            size: metadata.size as i64,
+            blksize: metadata.blksize as i32,
+            blocks: ((metadata.size + 511) / 512) as i64,
+            atime_sec,
+            atime_nsec,
+            mtime_sec,
+            mtime_nsec,
+            ctime_sec,
+            ctime_nsec,
+            ..Self::new(metadata.mode())
+        }
+    }
+}
+
+/// 纳秒数拆分为秒和纳秒
+fn split_ns(ns: usize) -> (i64, i64) {
+    ((ns / 1_000_000_000) as i64, (ns % 1_000_000_000) as i64)
 }
 
 bitflags! {
@@ -46,5 +236,26 @@ bitflags! {
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// fifo, 即管道
+        const FIFO  = 0o010000;
+        /// 字符设备
+        const CHR   = 0o020000;
+        /// 块设备
+        const BLK   = 0o060000;
+    }
+}
+
+impl StatMode {
+    /// 没有权限管理, 按文件类型给出固定的权限
+    pub fn default_permission(&self) -> u32 {
+        if *self == Self::DIR {
+            0o755
+        } else if *self == Self::CHR || *self == Self::BLK {
+            0o666
+        } else if *self == Self::FIFO {
+            0o600
+        } else {
+            0o644
+        }
     }
 }
@@ -0,0 +1,58 @@
+//! Path: 路径的规范化和拆分
+//! 内核中保存的路径都是规范的绝对路径: 以`/`开头, 不包含`.`, `..`和多余的`/`
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// 将path相对于cwd转换为规范的绝对路径
+/// 没有符号链接, 因此`..`可以直接按字面处理, 根目录的`..`仍然是根目录
+pub fn absolute_path(cwd: &str, path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    let base = if path.starts_with('/') { "" } else { cwd };
+
+    for component in base.split('/').chain(path.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+
+    let mut result = String::new();
+    for component in components {
+        result.push('/');
+        result.push_str(component);
+    }
+    if result.is_empty() {
+        result.push('/');
+    }
+    result
+}
+
+/// 规范路径的各个部分, 根目录没有任何部分
+pub fn path_components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|component| !component.is_empty())
+}
+
+/// 将规范路径拆分为父目录和最后一部分, 根目录返回None
+pub fn split_parent(path: &str) -> Option<(&str, &str)> {
+    let idx = path.rfind('/')?;
+    let name = &path[idx + 1..];
+    if name.is_empty() {
+        return None;
+    }
+    let parent = if idx == 0 { "/" } else { &path[..idx] };
+    Some((parent, name))
+}
+
+/// 拼接规范的目录路径和其中的文件名
+pub fn join_path(parent: &str, name: &str) -> String {
+    let mut path = String::from(parent);
+    if !path.ends_with('/') {
+        path.push('/');
+    }
+    path.push_str(name);
+    path
+}
@@ -0,0 +1,204 @@
+//! Pipe: 匿名管道, 读端和写端共享一个环形缓冲区
+
+use super::{File, Stat, StatMode};
+use crate::errno::Errno;
+use crate::mm::UserBuffer;
+use crate::sync::{SpinLock, WaitQueue};
+use crate::task::{current_process, SignalFlags};
+use alloc::sync::Arc;
+
+/// 环形缓冲区的大小
+const RING_BUFFER_SIZE: usize = 4096;
+
+/// 管道的一端
+pub struct Pipe {
+    readable: bool,
+    writable: bool,
+    shared: Arc<PipeShared>,
+}
+
+/// 读端和写端共享的部分
+struct PipeShared {
+    ring: SpinLock<PipeRingBuffer>,
+    /// 等待数据的读者
+    read_wait: WaitQueue,
+    /// 等待空闲空间的写者
+    write_wait: WaitQueue,
+}
+
+/// 环形缓冲区
+struct PipeRingBuffer {
+    arr: [u8; RING_BUFFER_SIZE],
+    head: usize,
+    /// 缓冲区中的字节数
+    len: usize,
+    /// 读端已经关闭
+    read_closed: bool,
+    /// 写端已经关闭, 读完剩余数据以后返回EOF
+    write_closed: bool,
+}
+
+impl PipeRingBuffer {
+    fn new() -> Self {
+        Self {
+            arr: [0; RING_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+            read_closed: false,
+            write_closed: false,
+        }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let c = self.arr[self.head];
+        self.head = (self.head + 1) % RING_BUFFER_SIZE;
+        self.len -= 1;
+        c
+    }
+
+    fn write_byte(&mut self, c: u8) {
+        self.arr[(self.head + self.len) % RING_BUFFER_SIZE] = c;
+        self.len += 1;
+    }
+
+    fn available_write(&self) -> usize {
+        RING_BUFFER_SIZE - self.len
+    }
+}
+
+/// 创建一个管道, 返回(读端, 写端)
+pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
+    let shared = Arc::new(PipeShared {
+        ring: SpinLock::new(PipeRingBuffer::new()),
+        read_wait: WaitQueue::new(),
+        write_wait: WaitQueue::new(),
+    });
+    let read_end = Arc::new(Pipe {
+        readable: true,
+        writable: false,
+        shared: shared.clone(),
+    });
+    let write_end = Arc::new(Pipe {
+        readable: false,
+        writable: true,
+        shared,
+    });
+    (read_end, write_end)
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+
+    fn writable(&self) -> bool {
+        self.writable
+    }
+
+    /// 缓冲区为空时阻塞, 写端全部关闭以后返回0, 等待期间被信号打断返回EINTR
+    fn read(&self, mut buf: UserBuffer) -> Result<usize, Errno> {
+        assert!(self.readable);
+        let want = buf.len();
+        if want == 0 {
+            return Ok(0);
+        }
+
+        loop {
+            let ready = self.shared.read_wait.wait_until_interruptible(|| {
+                let ring = self.shared.ring.lock();
+                ring.len > 0 || ring.write_closed
+            });
+            if !ready {
+                return Err(Errno::EINTR);
+            }
+
+            let mut ring = self.shared.ring.lock();
+            if ring.len == 0 {
+                if ring.write_closed {
+                    return Ok(0);
+                }
+                // 数据被其他读者取走了
+                continue;
+            }
+
+            let count = ring.len.min(want);
+            let mut read = 0;
+            'copy: for buffer in buf.buffers.iter_mut() {
+                for byte in buffer.iter_mut() {
+                    if read == count {
+                        break 'copy;
+                    }
+                    *byte = ring.read_byte();
+                    read += 1;
+                }
+            }
+            drop(ring);
+
+            self.shared.write_wait.wake_all();
+            return Ok(count);
+        }
+    }
+
+    /// 缓冲区已满时阻塞, 直到全部写入
+    /// 读端已经关闭时向当前任务发送SIGPIPE, 返回已经写入的字节数
+    /// 等待期间被信号打断时返回已经写入的字节数, 还没有写入时返回EINTR
+    fn write(&self, buf: UserBuffer) -> Result<usize, Errno> {
+        assert!(self.writable);
+        let total = buf.len();
+        let mut bytes = buf.buffers.iter().flat_map(|buffer| buffer.iter());
+        let mut written = 0;
+
+        while written < total {
+            let ready = self.shared.write_wait.wait_until_interruptible(|| {
+                let ring = self.shared.ring.lock();
+                ring.available_write() > 0 || ring.read_closed
+            });
+            if !ready {
+                return if written == 0 {
+                    Err(Errno::EINTR)
+                } else {
+                    Ok(written)
+                };
+            }
+
+            let mut ring = self.shared.ring.lock();
+            if ring.read_closed {
+                drop(ring);
+                current_process().send_signal(SignalFlags::SIGPIPE);
+                return Ok(written);
+            }
+
+            let count = ring.available_write().min(total - written);
+            for _ in 0..count {
+                ring.write_byte(*bytes.next().unwrap());
+            }
+            written += count;
+            drop(ring);
+
+            self.shared.read_wait.wake_all();
+        }
+
+        Ok(written)
+    }
+
+    fn get_stat(&self) -> Stat {
+        Stat::new(StatMode::FIFO)
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        let mut ring = self.shared.ring.lock();
+        if self.readable {
+            ring.read_closed = true;
+        }
+        if self.writable {
+            ring.write_closed = true;
+        }
+        drop(ring);
+
+        // 对端可能正在等待
+        self.shared.read_wait.wake_all();
+        self.shared.write_wait.wake_all();
+    }
+}
@@ -0,0 +1,290 @@
+//! procfs: 通过读取文件查看任务, 内存和系统调用的统计信息
+//! 文件内容在每次读取时重新生成, 因此大小总是0
+
+use super::vfs::{alloc_dev, FileSystem, Inode, InodeType, Metadata, SuperBlock};
+use crate::config::{CLOCK_FREQ, MAX_SYSCALL_NUM, PAGE_SIZE};
+use crate::mm::{frame_stats, MapPermission};
+use crate::task::{all_pids, pid2process, TaskStatus};
+use crate::timer::{get_time, get_time_ms, get_time_ns};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// 每个任务目录中的文件
+const PID_ENTRIES: [&str; 3] = ["status", "maps", "syscalls"];
+
+/// procfs文件系统
+pub struct ProcFileSystemType;
+
+/// 一个procfs实例
+struct ProcSuperBlock {
+    dev: u64,
+}
+
+/// procfs中的inode
+/// 任务目录和其中的文件只记录pid, 任务退出以后无法再读取
+#[derive(Clone, Copy)]
+enum ProcInode {
+    Root,
+    Pid(usize),
+    File(ProcFileKind),
+}
+
+#[derive(Clone, Copy)]
+enum ProcFileKind {
+    MemInfo,
+    Uptime,
+    Status(usize),
+    Maps(usize),
+    Syscalls(usize),
+}
+
+/// 带有设备号的procfs inode
+struct ProcNode {
+    dev: u64,
+    inode: ProcInode,
+}
+
+impl FileSystem for ProcFileSystemType {
+    fn name(&self) -> &'static str {
+        "proc"
+    }
+
+    /// source和data被忽略
+    fn mount(&self, _source: &str, _data: &str) -> Option<Arc<dyn SuperBlock>> {
+        Some(Arc::new(ProcSuperBlock { dev: alloc_dev() }))
+    }
+}
+
+impl SuperBlock for ProcSuperBlock {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        Arc::new(ProcNode {
+            dev: self.dev,
+            inode: ProcInode::Root,
+        })
+    }
+}
+
+impl ProcNode {
+    fn child(&self, inode: ProcInode) -> Arc<dyn Inode> {
+        Arc::new(ProcNode {
+            dev: self.dev,
+            inode,
+        })
+    }
+
+    /// inode编号: 根目录为1, 全局文件为2和3, 任务pid的目录和文件从(pid + 1) * 4开始
+    fn ino(&self) -> u64 {
+        let ino = match self.inode {
+            ProcInode::Root => 1,
+            ProcInode::File(ProcFileKind::MemInfo) => 2,
+            ProcInode::File(ProcFileKind::Uptime) => 3,
+            ProcInode::Pid(pid) => (pid + 1) * 4,
+            ProcInode::File(ProcFileKind::Status(pid)) => (pid + 1) * 4 + 1,
+            ProcInode::File(ProcFileKind::Maps(pid)) => (pid + 1) * 4 + 2,
+            ProcInode::File(ProcFileKind::Syscalls(pid)) => (pid + 1) * 4 + 3,
+        };
+        ino as u64
+    }
+}
+
+impl Inode for ProcNode {
+    /// 内容在读取时生成, 时间总是当前时间
+    fn metadata(&self) -> Metadata {
+        let now = get_time_ns();
+        Metadata {
+            dev: self.dev,
+            ino: self.ino(),
+            type_: match self.inode {
+                ProcInode::File(_) => InodeType::File,
+                _ => InodeType::Dir,
+            },
+            nlink: match self.inode {
+                ProcInode::File(_) => 1,
+                _ => 2,
+            },
+            size: 0,
+            blksize: PAGE_SIZE as u32,
+            rdev: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let content = match self.inode {
+            ProcInode::File(kind) => match kind.content() {
+                Some(content) => content,
+                None => return 0,
+            },
+            _ => return 0,
+        };
+        let bytes = content.as_bytes();
+        if offset >= bytes.len() {
+            return 0;
+        }
+        let len = buf.len().min(bytes.len() - offset);
+        buf[..len].copy_from_slice(&bytes[offset..offset + len]);
+        len
+    }
+
+    /// 生成内容时需要获取进程的锁, 而缺页处理时已经持有当前进程的锁
+    fn mmapable(&self) -> bool {
+        false
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        match self.inode {
+            ProcInode::Root => match name {
+                "meminfo" => Some(self.child(ProcInode::File(ProcFileKind::MemInfo))),
+                "uptime" => Some(self.child(ProcInode::File(ProcFileKind::Uptime))),
+                _ => {
+                    let pid = name.parse::<usize>().ok()?;
+                    // 不接受"01"这样的名字, 保证每个任务只有一个目录
+                    if pid.to_string() != name {
+                        return None;
+                    }
+                    pid2process(pid)?;
+                    Some(self.child(ProcInode::Pid(pid)))
+                }
+            },
+            ProcInode::Pid(pid) => {
+                let kind = match name {
+                    "status" => ProcFileKind::Status(pid),
+                    "maps" => ProcFileKind::Maps(pid),
+                    "syscalls" => ProcFileKind::Syscalls(pid),
+                    _ => return None,
+                };
+                Some(self.child(ProcInode::File(kind)))
+            }
+            ProcInode::File(_) => None,
+        }
+    }
+
+    fn list(&self) -> Vec<String> {
+        match self.inode {
+            ProcInode::Root => {
+                let mut names = vec![String::from("meminfo"), String::from("uptime")];
+                names.extend(all_pids().into_iter().map(|pid| pid.to_string()));
+                names
+            }
+            ProcInode::Pid(_) => PID_ENTRIES.iter().map(|name| String::from(*name)).collect(),
+            ProcInode::File(_) => Vec::new(),
+        }
+    }
+}
+
+impl ProcFileKind {
+    /// 生成文件内容, 任务已经退出时返回None
+    fn content(self) -> Option<String> {
+        let mut s = String::new();
+        match self {
+            ProcFileKind::MemInfo => {
+                let stats = frame_stats();
+                let kb = PAGE_SIZE / 1024;
+                writeln!(s, "MemTotal:\t{} kB", stats.total * kb).unwrap();
+                writeln!(s, "MemFree:\t{} kB", stats.free * kb).unwrap();
+                writeln!(s, "LargestFree:\t{} kB", stats.largest_free * kb).unwrap();
+                writeln!(s, "Fragmentation:\t{}%", stats.fragmentation()).unwrap();
+                write!(s, "FreeBlocks:").unwrap();
+                for count in stats.free_blocks.iter() {
+                    write!(s, " {}", count).unwrap();
+                }
+                writeln!(s).unwrap();
+            }
+            ProcFileKind::Uptime => {
+                let centis = get_time() / (CLOCK_FREQ / 100);
+                writeln!(s, "{}.{:02}", centis / 100, centis % 100).unwrap();
+            }
+            ProcFileKind::Status(pid) => {
+                let process = pid2process(pid)?;
+                let inner = process.inner_exclusive_access();
+                let ppid = inner
+                    .parent
+                    .as_ref()
+                    .and_then(|parent| parent.upgrade())
+                    .map_or(0, |parent| parent.get_pid());
+                // 调度相关的信息来自还没有退出的编号最小的线程, 通常是主线程
+                let task = inner
+                    .tasks
+                    .iter()
+                    .flatten()
+                    .find(|task| !task.inner_exclusive_access().is_exited())?
+                    .clone();
+                let task_inner = task.inner_exclusive_access();
+                let state = match task_inner.task_status {
+                    _ if inner.stopped => "T (stopped)",
+                    TaskStatus::UnInit | TaskStatus::Ready => "R (ready)",
+                    TaskStatus::Running => "R (running)",
+                    TaskStatus::Blocked => "S (sleeping)",
+                    TaskStatus::Zombie => "Z (zombie)",
+                };
+                let areas = inner.memory_set.areas();
+                let vm_size: usize = areas
+                    .iter()
+                    .map(|area| area.get_end().0 - area.get_start().0)
+                    .sum();
+                let vm_rss: usize = areas.iter().map(|area| area.resident_pages()).sum();
+                let info = &inner.task_info_inner;
+                let run_time = if info.first_run_flag {
+                    0
+                } else {
+                    get_time_ms().saturating_sub(info.first_run_time)
+                };
+
+                writeln!(s, "Pid:\t{}", pid).unwrap();
+                writeln!(s, "PPid:\t{}", ppid).unwrap();
+                writeln!(s, "State:\t{}", state).unwrap();
+                writeln!(s, "Threads:\t{}", inner.thread_count()).unwrap();
+                writeln!(s, "Cwd:\t{}", inner.cwd).unwrap();
+                writeln!(s, "Prio:\t{}", task_inner.prio).unwrap();
+                writeln!(s, "Stride:\t{}", task_inner.stride).unwrap();
+                writeln!(s, "SigPnd:\t{:016x}", inner.signals.bits()).unwrap();
+                writeln!(s, "SigBlk:\t{:016x}", task_inner.signal_mask.bits()).unwrap();
+                writeln!(s, "VmSize:\t{} kB", vm_size * PAGE_SIZE / 1024).unwrap();
+                writeln!(s, "VmRSS:\t{} kB", vm_rss * PAGE_SIZE / 1024).unwrap();
+                writeln!(s, "FDSize:\t{}", inner.fd_table.len()).unwrap();
+                writeln!(s, "RunTime:\t{} ms", run_time).unwrap();
+            }
+            ProcFileKind::Maps(pid) => {
+                let process = pid2process(pid)?;
+                let inner = process.inner_exclusive_access();
+                for area in inner.memory_set.areas() {
+                    let perm = area.perm();
+                    let flag =
+                        |bit: MapPermission, c: char| if perm.contains(bit) { c } else { '-' };
+                    let (offset, name) = match area.file_offset() {
+                        Some(offset) => (offset, " [file]"),
+                        None => (0, ""),
+                    };
+                    writeln!(
+                        s,
+                        "{:016x}-{:016x} {}{}{}{} {:08x}{}",
+                        area.get_start().0 * PAGE_SIZE,
+                        area.get_end().0 * PAGE_SIZE,
+                        flag(MapPermission::R, 'r'),
+                        flag(MapPermission::W, 'w'),
+                        flag(MapPermission::X, 'x'),
+                        if area.is_shared() { 's' } else { 'p' },
+                        offset,
+                        name,
+                    )
+                    .unwrap();
+                }
+            }
+            ProcFileKind::Syscalls(pid) => {
+                let process = pid2process(pid)?;
+                let info = process.inner_exclusive_access().task_info_inner;
+                for id in 0..MAX_SYSCALL_NUM {
+                    if info.syscall_times[id] != 0 {
+                        writeln!(s, "{}\t{}", id, info.syscall_times[id]).unwrap();
+                    }
+                }
+            }
+        }
+        Some(s)
+    }
+}
@@ -1,55 +1,101 @@
 //! Stdin & Stdout
-use super::File;
-use super::Stat;
+use super::vfs::makedev;
+use super::{File, Stat, StatMode};
+use crate::errno::Errno;
 use crate::mm::UserBuffer;
 use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+use crate::sync::{SpinLock, WaitQueue};
+use alloc::collections::VecDeque;
 
 /// 标准输入
 pub struct Stdin;
 /// 标准输出
 pub struct Stdout;
 
-impl File for Stdin {
-    fn readable(&self) -> bool {
-        true
+/// 已经从串口读到, 但是还没有被读取的字符
+static STDIN_BUFFER: SpinLock<VecDeque<u8>> = SpinLock::new(VecDeque::new());
+/// 等待输入的任务
+static STDIN_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// 有任务等待输入时读取串口, 读到字符以后唤醒它们
+/// 在时钟中断和空闲的核心上调用
+pub fn poll_stdin() {
+    if STDIN_WAIT_QUEUE.is_empty() {
+        return;
     }
 
-    fn writable(&self) -> bool {
-        false
+    let c = console_getchar();
+    if c == 0 {
+        return;
     }
 
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
-        // 一次只读一字节
-        assert_eq!(user_buf.len(), 1);
+    STDIN_BUFFER.lock().push_back(c as u8);
+    STDIN_WAIT_QUEUE.wake_all();
+}
 
-        let mut c: usize;
+/// 从控制台读取, 没有输入时阻塞, 之后读取已经到达的其余字符
+/// 等待输入时被信号打断返回EINTR
+pub fn console_read(mut user_buf: UserBuffer) -> Result<usize, Errno> {
+    if user_buf.len() == 0 {
+        return Ok(0);
+    }
 
-        loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
+    // 没有输入时阻塞, 直到poll_stdin读到字符
+    if !STDIN_WAIT_QUEUE.wait_until_interruptible(|| !STDIN_BUFFER.lock().is_empty()) {
+        return Err(Errno::EINTR);
+    }
+
+    let mut buffer = STDIN_BUFFER.lock();
+    let mut read = 0;
+    'copy: for slice in user_buf.buffers.iter_mut() {
+        for byte in slice.iter_mut() {
+            match buffer.pop_front() {
+                Some(c) => *byte = c,
+                None => break 'copy,
             }
+            read += 1;
         }
+    }
+    Ok(read)
+}
 
-        let ch = c as u8;
+/// 写入控制台
+pub fn console_write(buf: UserBuffer) -> usize {
+    for buffer in buf.buffers.iter() {
+        // 循环读
+        print!("{}", core::str::from_utf8(*buffer).unwrap());
+    }
 
-        unsafe {
-            user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
-        }
+    buf.len()
+}
 
-        1
+/// 控制台是字符设备, 设备号与/dev/console相同
+fn console_stat() -> Stat {
+    Stat {
+        rdev: makedev(5, 1),
+        ..Stat::new(StatMode::CHR)
+    }
+}
+
+impl File for Stdin {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
     }
 
-    fn write(&self, _buf: UserBuffer) -> usize {
+    fn read(&self, user_buf: UserBuffer) -> Result<usize, Errno> {
+        console_read(user_buf)
+    }
+
+    fn write(&self, _buf: UserBuffer) -> Result<usize, Errno> {
         panic!("Cannot write to stdin!");
     }
 
     fn get_stat(&self) -> Stat {
-        panic!("Cannot Access Stdin Stat!");
+        console_stat()
     }
 }
 
@@ -62,20 +108,15 @@ impl File for Stdout {
         true
     }
 
-    fn read(&self, _buf: UserBuffer) -> usize {
+    fn read(&self, _buf: UserBuffer) -> Result<usize, Errno> {
         panic!("Cannot read from stdout!");
     }
 
-    fn write(&self, buf: UserBuffer) -> usize {
-        for buffer in buf.buffers.iter() {
-            // 循环读
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
-        }
-
-        buf.len()
+    fn write(&self, buf: UserBuffer) -> Result<usize, Errno> {
+        Ok(console_write(buf))
     }
 
     fn get_stat(&self) -> Stat {
-        panic!("Cannot Access Stdout Stat!");
+        console_stat()
     }
 }
@@ -0,0 +1,363 @@
+//! tmpfs: 内存中的文件系统, 文件内容保存在物理页帧中, 卸载以后全部丢失
+
+use super::vfs::{alloc_dev, FileSystem, Inode, InodeType, Metadata, SuperBlock};
+use crate::config::{PAGE_SIZE, TMPFS_SIZE_LIMIT};
+use crate::mm::{frame_alloc, FrameTracker};
+use crate::sync::SpinLock;
+use crate::timer::get_time_ns;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// tmpfs文件系统, 挂载选项`size=<bytes>[k|m|g]`限制使用的内存
+pub struct TmpFileSystemType;
+
+/// 一个tmpfs实例中所有inode共享的部分
+/// inode不持有SuperBlock, 卸载以后已经打开的文件仍然可以访问
+struct TmpFsShared {
+    dev: u64,
+    /// 最多可以使用的页面数量
+    max_pages: usize,
+    /// 已经使用的页面数量
+    used_pages: AtomicUsize,
+    next_ino: AtomicU64,
+}
+
+impl TmpFsShared {
+    /// 在容量限制之内分配一个页面
+    fn alloc_page(&self) -> Option<FrameTracker> {
+        self.used_pages
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used < self.max_pages).then_some(used + 1)
+            })
+            .ok()?;
+        let frame = frame_alloc();
+        if frame.is_none() {
+            self.free_pages(1);
+        }
+        frame
+    }
+
+    fn free_pages(&self, count: usize) {
+        self.used_pages.fetch_sub(count, Ordering::Relaxed);
+    }
+}
+
+/// 一个tmpfs实例
+struct TmpFsSuperBlock {
+    root: Arc<TmpFsInode>,
+}
+
+/// tmpfs中的文件或者目录
+struct TmpFsInode {
+    ino: u64,
+    type_: InodeType,
+    shared: Arc<TmpFsShared>,
+    /// 内容都在内存中, mmap会在持有进程的锁时读写页面, 因此使用自旋锁
+    inner: SpinLock<TmpFsInodeInner>,
+}
+
+struct TmpFsInodeInner {
+    /// 指向该inode的目录项数量, 目录不使用
+    nlink: u32,
+    /// 最后访问, 修改内容, 修改元数据的时间
+    atime: usize,
+    mtime: usize,
+    ctime: usize,
+    content: TmpFsContent,
+}
+
+impl TmpFsInodeInner {
+    /// 内容被修改, 同时更新mtime和ctime
+    fn touch(&mut self) {
+        let now = get_time_ns();
+        self.mtime = now;
+        self.ctime = now;
+    }
+}
+
+enum TmpFsContent {
+    File {
+        size: usize,
+        /// 没有写入过的页面为None, 读取时为0
+        pages: Vec<Option<FrameTracker>>,
+    },
+    Dir(BTreeMap<String, Arc<TmpFsInode>>),
+}
+
+impl FileSystem for TmpFileSystemType {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    /// source被忽略, 每次挂载都是一个新的空文件系统
+    fn mount(&self, _source: &str, data: &str) -> Option<Arc<dyn SuperBlock>> {
+        let mut size = TMPFS_SIZE_LIMIT;
+        for option in data.split(',').filter(|option| !option.is_empty()) {
+            match option.strip_prefix("size=") {
+                Some(value) => size = parse_size(value)?,
+                None => return None,
+            }
+        }
+
+        let shared = Arc::new(TmpFsShared {
+            dev: alloc_dev(),
+            max_pages: size / PAGE_SIZE,
+            used_pages: AtomicUsize::new(0),
+            next_ino: AtomicU64::new(1),
+        });
+        let root = TmpFsInode::new(&shared, InodeType::Dir);
+        Some(Arc::new(TmpFsSuperBlock { root }))
+    }
+}
+
+/// 解析带有k/m/g后缀的字节数
+fn parse_size(value: &str) -> Option<usize> {
+    let (digits, shift) = match value.as_bytes().last()? {
+        b'k' | b'K' => (&value[..value.len() - 1], 10),
+        b'm' | b'M' => (&value[..value.len() - 1], 20),
+        b'g' | b'G' => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(1 << shift)
+}
+
+impl SuperBlock for TmpFsSuperBlock {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+impl TmpFsInode {
+    fn new(shared: &Arc<TmpFsShared>, type_: InodeType) -> Arc<Self> {
+        let now = get_time_ns();
+        let content = match type_ {
+            InodeType::File => TmpFsContent::File {
+                size: 0,
+                pages: Vec::new(),
+            },
+            InodeType::Dir => TmpFsContent::Dir(BTreeMap::new()),
+            InodeType::CharDevice | InodeType::BlockDevice => unreachable!(),
+        };
+        Arc::new(Self {
+            ino: shared.next_ino.fetch_add(1, Ordering::Relaxed),
+            type_,
+            shared: shared.clone(),
+            inner: SpinLock::new(TmpFsInodeInner {
+                nlink: 1,
+                atime: now,
+                mtime: now,
+                ctime: now,
+                content,
+            }),
+        })
+    }
+}
+
+impl TmpFsContent {
+    /// 将文件截断为len字节, 释放之后的页面
+    fn truncate(&mut self, shared: &TmpFsShared, len: usize) {
+        if let TmpFsContent::File { size, pages } = self {
+            if len >= *size {
+                return;
+            }
+            let keep = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+            let freed = pages.drain(keep.min(pages.len())..).flatten().count();
+            shared.free_pages(freed);
+            // 最后一页中被截断的部分需要清零, 之后扩展文件时读到0
+            if let Some(Some(frame)) = pages.get(len / PAGE_SIZE) {
+                frame.ppn.get_bytes_array()[len % PAGE_SIZE..].fill(0);
+            }
+            *size = len;
+        }
+    }
+}
+
+impl Inode for TmpFsInode {
+    fn metadata(&self) -> Metadata {
+        let inner = self.inner.lock();
+        let (nlink, size) = match &inner.content {
+            TmpFsContent::File { size, .. } => (inner.nlink, *size),
+            TmpFsContent::Dir(children) => {
+                let subdirs = children
+                    .values()
+                    .filter(|child| child.type_ == InodeType::Dir)
+                    .count();
+                (2 + subdirs as u32, 0)
+            }
+        };
+        Metadata {
+            dev: self.shared.dev,
+            ino: self.ino,
+            type_: self.type_,
+            nlink,
+            size,
+            blksize: PAGE_SIZE as u32,
+            rdev: 0,
+            atime: inner.atime,
+            mtime: inner.mtime,
+            ctime: inner.ctime,
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let mut inner = self.inner.lock();
+        inner.atime = get_time_ns();
+        let (size, pages) = match &inner.content {
+            TmpFsContent::File { size, pages } => (*size, pages),
+            TmpFsContent::Dir(_) => return 0,
+        };
+        if offset >= size {
+            return 0;
+        }
+
+        let len = buf.len().min(size - offset);
+        let mut read = 0;
+        while read < len {
+            let pos = offset + read;
+            let page_offset = pos % PAGE_SIZE;
+            let count = (PAGE_SIZE - page_offset).min(len - read);
+            let dst = &mut buf[read..read + count];
+            match pages.get(pos / PAGE_SIZE) {
+                Some(Some(frame)) => dst.copy_from_slice(
+                    &frame.ppn.get_bytes_array()[page_offset..page_offset + count],
+                ),
+                _ => dst.fill(0),
+            }
+            read += count;
+        }
+        len
+    }
+
+    /// 容量不足时只写入一部分
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut inner = self.inner.lock();
+        let (size, pages) = match &mut inner.content {
+            TmpFsContent::File { size, pages } => (size, pages),
+            TmpFsContent::Dir(_) => return 0,
+        };
+
+        let mut written = 0;
+        while written < buf.len() {
+            let pos = offset + written;
+            let page_index = pos / PAGE_SIZE;
+            let page_offset = pos % PAGE_SIZE;
+            let count = (PAGE_SIZE - page_offset).min(buf.len() - written);
+
+            // 文件大小也不能超过容量, 避免页面数组过大
+            if page_index >= self.shared.max_pages {
+                break;
+            }
+            if pages.len() <= page_index {
+                pages.resize_with(page_index + 1, || None);
+            }
+            if pages[page_index].is_none() {
+                match self.shared.alloc_page() {
+                    Some(frame) => pages[page_index] = Some(frame),
+                    None => break,
+                }
+            }
+            let frame = pages[page_index].as_ref().unwrap();
+            frame.ppn.get_bytes_array()[page_offset..page_offset + count]
+                .copy_from_slice(&buf[written..written + count]);
+            written += count;
+        }
+
+        if written > 0 {
+            *size = (*size).max(offset + written);
+            inner.touch();
+        }
+        written
+    }
+
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.content.truncate(&self.shared, 0);
+        inner.touch();
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        match &self.inner.lock().content {
+            TmpFsContent::Dir(children) => children
+                .get(name)
+                .map(|child| child.clone() as Arc<dyn Inode>),
+            TmpFsContent::File { .. } => None,
+        }
+    }
+
+    fn create(&self, name: &str, type_: InodeType) -> Option<Arc<dyn Inode>> {
+        let mut inner = self.inner.lock();
+        let children = match &mut inner.content {
+            TmpFsContent::Dir(children) => children,
+            TmpFsContent::File { .. } => return None,
+        };
+        // 不支持设备文件
+        if children.contains_key(name) || !matches!(type_, InodeType::File | InodeType::Dir) {
+            return None;
+        }
+        let child = TmpFsInode::new(&self.shared, type_);
+        children.insert(String::from(name), child.clone());
+        inner.touch();
+        Some(child)
+    }
+
+    fn link(&self, old_name: &str, new_name: &str) -> bool {
+        let mut inner = self.inner.lock();
+        let children = match &mut inner.content {
+            TmpFsContent::Dir(children) => children,
+            TmpFsContent::File { .. } => return false,
+        };
+        if children.contains_key(new_name) {
+            return false;
+        }
+        let child = match children.get(old_name) {
+            Some(child) if child.type_ == InodeType::File => child.clone(),
+            _ => return false,
+        };
+        {
+            let mut child_inner = child.inner.lock();
+            child_inner.nlink += 1;
+            child_inner.ctime = get_time_ns();
+        }
+        children.insert(String::from(new_name), child);
+        inner.touch();
+        true
+    }
+
+    /// 已经打开的文件在关闭之前仍然可以读写, 最后一个引用消失时释放页面
+    fn unlink(&self, name: &str) -> bool {
+        let mut inner = self.inner.lock();
+        let children = match &mut inner.content {
+            TmpFsContent::Dir(children) => children,
+            TmpFsContent::File { .. } => return false,
+        };
+        let child = match children.remove(name) {
+            Some(child) => child,
+            None => return false,
+        };
+        inner.touch();
+        let mut child_inner = child.inner.lock();
+        child_inner.nlink = child_inner.nlink.saturating_sub(1);
+        child_inner.ctime = get_time_ns();
+        true
+    }
+
+    fn list(&self) -> Vec<String> {
+        match &self.inner.lock().content {
+            TmpFsContent::Dir(children) => children.keys().cloned().collect(),
+            TmpFsContent::File { .. } => Vec::new(),
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        self.type_ == InodeType::Dir
+    }
+}
+
+impl Drop for TmpFsInode {
+    fn drop(&mut self) {
+        self.inner.lock().content.truncate(&self.shared, 0);
+    }
+}
@@ -0,0 +1,276 @@
+//! VFS: 文件系统的统一接口, 以及挂载表和路径解析
+//! 各个文件系统实现Inode/SuperBlock/FileSystem, 通过挂载表组成一棵目录树
+
+use super::devfs::DevFileSystemType;
+use super::easyfs::EasyFileSystemType;
+use super::path::path_components;
+use super::procfs::ProcFileSystemType;
+use super::tmpfs::TmpFileSystemType;
+use super::{File, StatMode};
+use crate::errno::Errno;
+use crate::sync::SpinLock;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::*;
+
+/// inode的类型
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum InodeType {
+    /// 普通文件
+    File,
+    /// 目录
+    Dir,
+    /// 字符设备
+    CharDevice,
+    /// 块设备
+    BlockDevice,
+}
+
+/// inode的元数据
+#[derive(Copy, Clone, Debug)]
+pub struct Metadata {
+    /// 所在文件系统的设备号, 每次挂载时分配
+    pub dev: u64,
+    /// inode编号, 在同一个文件系统中唯一
+    pub ino: u64,
+    /// 类型
+    pub type_: InodeType,
+    /// 硬链接数量
+    pub nlink: u32,
+    /// 文件大小
+    pub size: usize,
+    /// 文件系统的块大小
+    pub blksize: u32,
+    /// 设备文件的设备号, 其他文件为0
+    pub rdev: u64,
+    /// 最后访问时间, 开机以来的纳秒数, 没有记录时为0
+    pub atime: usize,
+    /// 最后修改内容的时间
+    pub mtime: usize,
+    /// 最后修改元数据的时间
+    pub ctime: usize,
+}
+
+impl Metadata {
+    /// stat中的文件类型
+    pub fn mode(&self) -> StatMode {
+        match self.type_ {
+            InodeType::File => StatMode::FILE,
+            InodeType::Dir => StatMode::DIR,
+            InodeType::CharDevice => StatMode::CHR,
+            InodeType::BlockDevice => StatMode::BLK,
+        }
+    }
+}
+
+/// 文件系统中的一个文件或者目录
+/// 目录操作的默认实现都会失败, 只读的文件系统不需要实现它们
+pub trait Inode: Send + Sync {
+    /// 元数据
+    fn metadata(&self) -> Metadata;
+    /// 从offset开始读取, 返回读到的字节数
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+    /// 从offset开始写入, 返回写入的字节数
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    /// 清空文件
+    fn clear(&self) {}
+    /// 在目录中查找name
+    fn lookup(&self, _name: &str) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    /// 在目录中创建name
+    fn create(&self, _name: &str, _type_: InodeType) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    /// 在目录中为old_name创建硬链接new_name
+    fn link(&self, _old_name: &str, _new_name: &str) -> bool {
+        false
+    }
+    /// 删除目录中的name
+    fn unlink(&self, _name: &str) -> bool {
+        false
+    }
+    /// 目录中的所有文件名, 不包括`.`和`..`
+    fn list(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// 设备文件打开时使用设备自己的File, 不允许这样打开时返回错误, 普通文件和目录返回None
+    fn open_device(
+        &self,
+        _readable: bool,
+        _writable: bool,
+    ) -> Option<Result<Arc<dyn File + Send + Sync>, Errno>> {
+        None
+    }
+
+    /// 是否是目录
+    fn is_dir(&self) -> bool {
+        self.metadata().type_ == InodeType::Dir
+    }
+
+    /// 是否可以被mmap, 缺页时会在持有进程的锁时调用read_at
+    /// 内容由内核动态生成, 读取时需要获取其他锁的文件应当返回false
+    fn mmapable(&self) -> bool {
+        self.metadata().type_ == InodeType::File
+    }
+}
+
+/// 一个挂载的文件系统实例
+pub trait SuperBlock: Send + Sync {
+    /// 根目录
+    fn root_inode(&self) -> Arc<dyn Inode>;
+}
+
+/// 一种文件系统, 通过名字在sys_mount中使用
+pub trait FileSystem: Send + Sync {
+    /// 文件系统的名字
+    fn name(&self) -> &'static str;
+    /// 创建一个文件系统实例, source和挂载选项data的含义由文件系统决定
+    fn mount(&self, source: &str, data: &str) -> Option<Arc<dyn SuperBlock>>;
+}
+
+/// 按照Linux的编码方式组合主设备号和次设备号
+pub fn makedev(major: u64, minor: u64) -> u64 {
+    ((major & 0xfff) << 8) | ((major & !0xfff) << 32) | (minor & 0xff) | ((minor & !0xff) << 12)
+}
+
+/// 分配一个设备号
+pub fn alloc_dev() -> u64 {
+    static NEXT_DEV: AtomicU64 = AtomicU64::new(1);
+    NEXT_DEV.fetch_add(1, Ordering::Relaxed)
+}
+
+/// 挂载表中的一项
+struct Mount {
+    /// 挂载点, 规范的绝对路径
+    path: String,
+    sb: Arc<dyn SuperBlock>,
+}
+
+impl Mount {
+    /// path在该挂载点之下时, 返回剩余的部分
+    fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if self.path == "/" {
+            return Some(path);
+        }
+        let rest = path.strip_prefix(self.path.as_str())?;
+        if rest.is_empty() || rest.starts_with('/') {
+            Some(rest)
+        } else {
+            None
+        }
+    }
+}
+
+lazy_static! {
+    /// 已经注册的文件系统
+    static ref FILE_SYSTEMS: SpinLock<Vec<Arc<dyn FileSystem>>> =
+        SpinLock::new(vec![
+            Arc::new(EasyFileSystemType),
+            Arc::new(TmpFileSystemType),
+            Arc::new(ProcFileSystemType),
+            Arc::new(DevFileSystemType),
+        ]);
+    /// 挂载表, 根目录是块设备上的easy-fs
+    static ref MOUNTS: SpinLock<Vec<Mount>> = SpinLock::new(vec![Mount {
+        path: String::from("/"),
+        sb: EasyFileSystemType.mount("", "").unwrap(),
+    }]);
+}
+
+/// 注册一种文件系统
+pub fn register_filesystem(fs: Arc<dyn FileSystem>) {
+    FILE_SYSTEMS.lock().push(fs);
+}
+
+/// 根据规范的绝对路径查找inode
+/// 使用路径最长的挂载点, 然后在其中逐级查找
+pub fn lookup(path: &str) -> Option<Arc<dyn Inode>> {
+    let (mut inode, rest) = {
+        let mounts = MOUNTS.lock();
+        let (mount, rest) = mounts
+            .iter()
+            .filter_map(|mount| mount.strip(path).map(|rest| (mount, rest)))
+            .max_by_key(|(mount, _)| mount.path.len())?;
+        (mount.sb.root_inode(), String::from(rest))
+    };
+
+    for component in path_components(&rest) {
+        if !inode.is_dir() {
+            return None;
+        }
+        inode = inode.lookup(component)?;
+    }
+    Some(inode)
+}
+
+/// path是否是挂载点
+pub fn is_mount_point(path: &str) -> bool {
+    MOUNTS.lock().iter().any(|mount| mount.path == path)
+}
+
+/// 直接位于目录path中的挂载点的名字
+/// 挂载点在下层文件系统中不一定存在, 列目录时需要补上
+pub fn mount_points_in(path: &str) -> Vec<String> {
+    MOUNTS
+        .lock()
+        .iter()
+        .filter_map(|mount| {
+            let (parent, name) = super::path::split_parent(&mount.path)?;
+            (parent == path).then(|| String::from(name))
+        })
+        .collect()
+}
+
+/// 将名为fs_name的文件系统挂载到target
+/// target的父目录必须存在, target本身可以不存在, 但是不能是文件或者已有的挂载点
+pub fn mount(source: &str, target: &str, fs_name: &str, data: &str) -> Result<(), Errno> {
+    let fs = match FILE_SYSTEMS.lock().iter().find(|fs| fs.name() == fs_name) {
+        Some(fs) => fs.clone(),
+        None => return Err(Errno::ENODEV),
+    };
+
+    // 根目录不能重新挂载
+    let (parent, _) = super::path::split_parent(target).ok_or(Errno::EBUSY)?;
+    match lookup(parent) {
+        Some(parent) if parent.is_dir() => {}
+        Some(_) => return Err(Errno::ENOTDIR),
+        None => return Err(Errno::ENOENT),
+    }
+    if lookup(target).map_or(false, |inode| !inode.is_dir()) {
+        return Err(Errno::ENOTDIR);
+    }
+
+    let sb = fs.mount(source, data).ok_or(Errno::EINVAL)?;
+    let mut mounts = MOUNTS.lock();
+    if mounts.iter().any(|mount| mount.path == target) {
+        return Err(Errno::EBUSY);
+    }
+    mounts.push(Mount {
+        path: String::from(target),
+        sb,
+    });
+    Ok(())
+}
+
+/// 卸载target上的文件系统, 其下还有其他挂载点时失败
+pub fn umount(target: &str) -> Result<(), Errno> {
+    let mut mounts = MOUNTS.lock();
+    let idx = mounts
+        .iter()
+        .position(|mount| mount.path == target)
+        .ok_or(Errno::EINVAL)?;
+    let prefix = mounts[idx].path.clone() + "/";
+    if target == "/" || mounts.iter().any(|mount| mount.path.starts_with(&prefix)) {
+        return Err(Errno::EBUSY);
+    }
+    // 已经打开的文件仍然持有文件系统的引用
+    mounts.remove(idx);
+    Ok(())
+}
@@ -18,6 +18,7 @@ extern crate alloc;
 mod console;
 pub mod config;
 pub mod drivers;
+pub mod errno;
 pub mod fs;
 pub mod lang_item;
 pub mod logging;
@@ -29,7 +30,8 @@ pub mod task;
 pub mod timer;
 pub mod trap;
 
-use task::add_initproc;
+use config::MAX_HART_NUM;
+use task::{add_initproc, hart_id};
 
 core::arch::global_asm!(include_str!("entry.asm"));
 core::arch::global_asm!(include_str!("link_app.S"));
@@ -54,6 +56,7 @@ pub fn rust_main() -> ! {
 
     mm::init();
     mm::remap_test();
+    fs::init();
 
     add_initproc();
     println!("[Kernel] After initproc!");
@@ -62,11 +65,39 @@ pub fn rust_main() -> ! {
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
     fs::list_apps();
+    start_secondary_harts();
     task::run_tasks();
 
     panic!("unreachable in rust_main!");
 }
 
+/// 通过SBI HSM启动其他核心, 它们从_start_secondary开始执行
+fn start_secondary_harts() {
+    extern "C" {
+        fn _start_secondary();
+    }
+
+    let boot_hart = hart_id();
+    for hartid in (0..MAX_HART_NUM).filter(|&hartid| hartid != boot_hart) {
+        if sbi::hart_start(hartid, _start_secondary as usize, 0) {
+            info!("[Kernel] hart {} is starting", hartid);
+        }
+    }
+}
+
+#[no_mangle]
+/// 其他核心的入口, 启动核心已经完成了全局的初始化
+pub fn rust_main_secondary() -> ! {
+    mm::init_secondary();
+    trap::init();
+    trap::enable_timer_interrupt();
+    timer::set_next_trigger();
+    println!("[Kernel] hart {} started!", hart_id());
+    task::run_tasks();
+
+    panic!("unreachable in rust_main_secondary!");
+}
+
 /// show os-elf segment
 fn show_os_sections() {
     extern "C" {
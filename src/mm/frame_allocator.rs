@@ -1,73 +1,148 @@
 //! Physical Page Frame Allocator的实现
 
-use super::PhysPageNum;
-use crate::{config::MEMORY_END, mm::PhysAddr, sync::UPSafeCell};
+use super::{swap::swap_out, PhysPageNum};
+use crate::{config::MEMORY_END, mm::PhysAddr, sync::SpinLock};
+use alloc::collections::BTreeSet;
 use alloc::vec::Vec;
 use lazy_static::*;
 
+/// 伙伴系统中最大块的阶数, 最大块包含2^MAX_ORDER个页面
+const MAX_ORDER: usize = 10;
+
 trait FrameAllocator {
     fn new() -> Self;
-    fn alloc(&mut self) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
 }
 
-/// Frame Allocator，用于将内核剩余物理地址以页面形式分配
-pub struct StackFrameAllocator {
-    current: usize,
+/// 伙伴系统Frame Allocator
+/// 阶数为k的块包含2^k个页面, 起始PPN按2^k对齐
+pub struct BuddyFrameAllocator {
+    /// 可分配的PPN范围[start, end)
+    start: usize,
     end: usize,
-    recycled: Vec<usize>,
+    /// 每个阶数的空闲块的起始PPN
+    free_lists: [BTreeSet<usize>; MAX_ORDER + 1],
 }
 
-impl FrameAllocator for StackFrameAllocator {
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
+            start: 0,
             end: 0,
-            recycled: Vec::new(),
-        }
-    }
-
-    // 实际上并没有真实转换物理地址为PPN
-    // 以一个类似于位图的结构控制分配
-    fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            // 先查找recycled中是否有之前回收的物理页
-            Some(ppn.into())
-        } else {
-            if self.current == self.end {
-                // 分配完了
-                None
-            } else {
-                self.current += 1;
-                Some((self.current - 1).into())
-            }
+            free_lists: Default::default(),
         }
     }
 
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
-        if ppn >= self.current || self.recycled.iter().find(|&v| *v == ppn).is_some() {
+        if ppn < self.start || ppn >= self.end || self.find_free_block(ppn).is_some() {
             panic!("Frame ppn {:#x} has not been allocated!", ppn);
         }
-        // 回收
-        self.recycled.push(ppn);
+        self.insert_block(ppn, 0);
     }
 }
 
-impl StackFrameAllocator {
+impl BuddyFrameAllocator {
     /// 以PPN的粒度进行分配
+    /// 将[l, r)划分为尽可能大的对齐块
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
+        self.start = l.0;
         self.end = r.0;
+
+        let mut ppn = l.0;
+        while ppn < r.0 {
+            let mut order = MAX_ORDER.min(ppn.trailing_zeros() as usize);
+            while ppn + (1 << order) > r.0 {
+                order -= 1;
+            }
+            self.free_lists[order].insert(ppn);
+            ppn += 1 << order;
+        }
+    }
+
+    /// 分配一个阶数为order的块, 返回起始PPN
+    fn alloc_order(&mut self, order: usize) -> Option<PhysPageNum> {
+        // 找到能满足要求的最小的块
+        let found = (order..=MAX_ORDER).find(|&k| !self.free_lists[k].is_empty())?;
+        let ppn = self.free_lists[found].pop_first().unwrap();
+
+        // 将多余的后一半依次放回对应的空闲链表
+        for k in (order..found).rev() {
+            self.free_lists[k].insert(ppn + (1 << k));
+        }
+
+        Some(ppn.into())
+    }
+
+    /// 放回一个阶数为order的块, 与空闲的伙伴块合并
+    fn insert_block(&mut self, mut ppn: usize, mut order: usize) {
+        while order < MAX_ORDER {
+            let buddy = ppn ^ (1 << order);
+            if !self.free_lists[order].remove(&buddy) {
+                break;
+            }
+            ppn = ppn.min(buddy);
+            order += 1;
+        }
+        self.free_lists[order].insert(ppn);
+    }
+
+    /// 查找包含ppn的空闲块, 返回(起始PPN, 阶数)
+    fn find_free_block(&self, ppn: usize) -> Option<(usize, usize)> {
+        (0..=MAX_ORDER)
+            .map(|order| (ppn & !((1 << order) - 1), order))
+            .find(|(start, order)| self.free_lists[*order].contains(start))
+    }
+
+    /// 统计空闲块的分布
+    fn stats(&self) -> FrameStats {
+        let mut free_blocks = [0; MAX_ORDER + 1];
+        for (order, list) in self.free_lists.iter().enumerate() {
+            free_blocks[order] = list.len();
+        }
+
+        FrameStats {
+            total: self.end - self.start,
+            free: (0..=MAX_ORDER).map(|k| free_blocks[k] << k).sum(),
+            largest_free: (0..=MAX_ORDER)
+                .rev()
+                .find(|&k| free_blocks[k] > 0)
+                .map_or(0, |k| 1 << k),
+            free_blocks,
+        }
+    }
+}
+
+/// 物理页面的使用情况
+#[derive(Debug)]
+pub struct FrameStats {
+    /// 可分配的页面总数
+    pub total: usize,
+    /// 空闲页面数
+    pub free: usize,
+    /// 最大空闲块包含的页面数
+    pub largest_free: usize,
+    /// 每个阶数的空闲块数量
+    pub free_blocks: [usize; MAX_ORDER + 1],
+}
+
+impl FrameStats {
+    /// 外部碎片率(百分比): 空闲页面中不属于最大空闲块的比例
+    pub fn fragmentation(&self) -> usize {
+        if self.free == 0 {
+            0
+        } else {
+            100 - self.largest_free * 100 / self.free
+        }
     }
 }
 
 // 定义了类型别名
-type FrameAllocatorImpl = StackFrameAllocator;
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
 lazy_static! {
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
-        unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
+    pub static ref FRAME_ALLOCATOR: SpinLock<FrameAllocatorImpl> =
+        SpinLock::new(FrameAllocatorImpl::new());
 }
 
 /// init 函数
@@ -78,7 +153,7 @@ pub fn init_frame_allocator() {
 
     // 可用数据范围
     // [ceil(ekernel as usize), floor(MEMORY_END)]
-    FRAME_ALLOCATOR.exclusive_access().init(
+    FRAME_ALLOCATOR.lock().init(
         PhysAddr::from(ekernel as usize).ceil(),
         PhysAddr::from(MEMORY_END).floor(),
     );
@@ -115,15 +190,87 @@ impl Drop for FrameTracker {
 }
 
 /// 分配一个FrameTracker, 同时分配ppn
+/// 物理页面不足时, 先换出一个用户页面再重新分配
 pub fn frame_alloc() -> Option<FrameTracker> {
-    // 如果alloc返回None，那么Map也会返回None
-    FRAME_ALLOCATOR
-        .exclusive_access()
-        .alloc()
-        .map(|ppn| FrameTracker::new(ppn))
+    alloc_order_or_swap(0).map(|ppn| FrameTracker::new(ppn))
+}
+
+/// 分配pages个物理地址连续的页面
+/// 起始PPN按pages向上取整到2的幂对齐, 例如用于DMA
+pub fn frame_alloc_contiguous(pages: usize) -> Option<Vec<FrameTracker>> {
+    let order = pages.next_power_of_two().trailing_zeros() as usize;
+    if pages == 0 || order > MAX_ORDER {
+        return None;
+    }
+
+    let ppn = alloc_order_or_swap(order)?;
+    // 多余的页面直接放回
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    for i in pages..(1 << order) {
+        allocator.dealloc((ppn.0 + i).into());
+    }
+    drop(allocator);
+
+    Some(
+        (0..pages)
+            .map(|i| FrameTracker::new((ppn.0 + i).into()))
+            .collect(),
+    )
+}
+
+/// 分配一个阶数为order的块
+/// 物理页面不足时不断换出用户页面, 直到分配成功或者没有页面可以换出
+fn alloc_order_or_swap(order: usize) -> Option<PhysPageNum> {
+    loop {
+        // 如果alloc返回None，那么Map也会返回None
+        if let Some(ppn) = FRAME_ALLOCATOR.lock().alloc_order(order) {
+            return Some(ppn);
+        }
+        if !swap_out() {
+            return None;
+        }
+    }
 }
 
 /// 回收FrameTracker
 pub fn frame_dealloc(ppn: PhysPageNum) {
-    FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
+    FRAME_ALLOCATOR.lock().dealloc(ppn);
+}
+
+/// 物理页面的使用情况
+pub fn frame_stats() -> FrameStats {
+    FRAME_ALLOCATOR.lock().stats()
+}
+
+/// frame allocator测试函数
+#[allow(unused)]
+pub fn frame_allocator_test() {
+    let before = frame_stats();
+
+    // 连续分配的页面按大小对齐
+    let frames = frame_alloc_contiguous(5).unwrap();
+    assert_eq!(frames.len(), 5);
+    assert_eq!(frames[0].ppn.0 % 8, 0);
+    for (i, frame) in frames.iter().enumerate() {
+        assert_eq!(frame.ppn.0, frames[0].ppn.0 + i);
+    }
+
+    // 单页分配不会落在已经分配的连续区域中
+    let single = frame_alloc().unwrap();
+    assert!(frames.iter().all(|frame| frame.ppn != single.ppn));
+
+    drop(frames);
+    drop(single);
+
+    // 全部回收以后伙伴块重新合并
+    let after = frame_stats();
+    assert_eq!(before.free, after.free);
+    assert_eq!(before.largest_free, after.largest_free);
+
+    println!(
+        "[Kernel] frame_allocator_test passed! free {}/{} frames, fragmentation {}%",
+        after.free,
+        after.total,
+        after.fragmentation()
+    );
 }
@@ -1,22 +1,26 @@
 //! Implementation of MapArea and MemorySet
 
-use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use alloc::{collections::btree_map::BTreeMap, string::String, sync::Arc, vec::Vec};
 use lazy_static::lazy_static;
 use riscv::register::satp;
 
 use crate::{
     config::{
-        KERNEL_STACK_SIZE, MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT_BASE,
+        KERNEL_STACK_SIZE, MEMORY_END, MMAP_BASE, MMIO, PAGE_SIZE, TRAMPOLINE, USER_SPACE_END,
         USER_STACK_SIZE,
     },
+    errno::Errno,
     mm::address::StepByOne,
-    sync::UPSafeCell,
+    sbi::remote_sfence_vma_all,
+    sync::SpinLock,
 };
 
 use super::{
     address::{VPNRange, VirtAddr, VirtPageNum},
     frame_allocator::{frame_alloc, FrameTracker},
+    mmap::MmapFile,
     page_table::{PTEFlags, PageTable, PageTableEntry},
+    swap::{register_page, Page},
     PhysAddr, PhysPageNum,
 };
 
@@ -36,9 +40,17 @@ extern "C" {
 /// 连续的Map地址
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// 使用引用计数，写时复制的页面在父子进程之间共享
+    /// 用户页面可能被换出到交换区
+    data_frames: BTreeMap<VirtPageNum, Arc<Page>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// 按需分配: 只保留地址范围, 第一次访问时才分配物理页面
+    lazy: bool,
+    /// MAP_SHARED: fork以后父子进程共享页面而不是写时复制, 文件映射的修改会写回文件
+    shared: bool,
+    /// 文件映射, 缺页时从文件读入页面
+    file: Option<MmapFile>,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -83,7 +95,7 @@ impl MemorySet {
     fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
         map_area.map(&mut self.page_table);
         if let Some(data) = data {
-            map_area.copy_data(&self.page_table, data);
+            map_area.copy_data(data);
         }
         self.areas.push(map_area);
     }
@@ -101,6 +113,43 @@ impl MemorySet {
         );
     }
 
+    /// 插入一个mmap区域, 此时不会分配物理页面
+    pub fn insert_mmap_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        shared: bool,
+        file: Option<MmapFile>,
+    ) {
+        self.push(
+            MapArea::new_mmap(start_va, end_va, permission, shared, file),
+            None,
+        );
+    }
+
+    /// 从MMAP_BASE开始查找一段长度为len的空闲地址
+    pub fn find_free_area(&self, len: usize) -> VirtAddr {
+        let mut start_va = VirtAddr::from(MMAP_BASE);
+        while let Some(area) = self.areas.iter().find(|area| {
+            area.get_start() < VirtAddr::from(start_va.0 + len).ceil()
+                && start_va.floor() < area.get_end()
+        }) {
+            start_va = area.get_end().into();
+        }
+        start_va
+    }
+
+    /// 判断[start_va, end_va)是否与已有的区域重叠
+    pub fn is_overlapping(&self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
+        let start_vpn = start_va.floor();
+        let end_vpn = end_va.ceil();
+
+        self.areas
+            .iter()
+            .any(|area| area.get_start() < end_vpn && start_vpn < area.get_end())
+    }
+
     /// 创建内核地址空间
     /// 每个segment都是页对齐的，因此不会重叠
     pub fn new_kernel() -> Self {
@@ -274,8 +323,9 @@ impl MemorySet {
         );
 
         // used for sbrk
+        // 堆空间按需分配
         memort_set.push(
-            MapArea::new(
+            MapArea::new_lazy(
                 user_stack_top.into(),
                 user_stack_top.into(),
                 MapType::Framed,
@@ -284,17 +334,7 @@ impl MemorySet {
             None,
         );
 
-        // map trapcontext
-        memort_set.push(
-            MapArea::new(
-                TRAP_CONTEXT_BASE.into(),
-                TRAMPOLINE.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
-
+        // Trap上下文属于线程, 创建线程时再映射
         (
             memort_set,
             user_stack_top,
@@ -307,8 +347,18 @@ impl MemorySet {
         self.page_table.token()
     }
 
+    /// 地址空间中的所有区域
+    pub fn areas(&self) -> &[MapArea] {
+        &self.areas
+    }
+
     /// remove all
     pub fn recycle_data_pages(&mut self) {
+        // 共享的页面可能还被其他地址空间引用, 需要先删除当前地址空间的映射
+        for area in self.areas.iter_mut() {
+            area.sync();
+            area.release(&self.page_table);
+        }
         self.areas.clear();
     }
 
@@ -338,19 +388,67 @@ impl MemorySet {
         self.page_table.translate(vpn)
     }
 
-    /// 保证区域一定都在页表当中
-    pub fn munmap_area(&mut self, start_va: VirtAddr, end_va: VirtAddr) {
+    /// 用户地址va所在的MAP_SHARED页面, 不属于MAP_SHARED区域或者还没有分配页面时返回None
+    /// MAP_SHARED区域不会写时复制, 页面被所有映射共享
+    pub fn shared_page(&self, va: VirtAddr) -> Option<Arc<Page>> {
+        let vpn = va.floor();
+        self.areas
+            .iter()
+            .find(|area| area.contains(vpn) && area.shared)
+            .and_then(|area| area.data_frames.get(&vpn).cloned())
+    }
+
+    /// 取消[start_va, end_va)中的映射, 部分重叠的区域被拆分
+    /// 范围中没有任何映射, 或者有用户态不能访问的区域时返回false
+    pub fn munmap_area(&mut self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
+        self.is_overlapping(start_va, end_va) && self.unmap_range(start_va, end_va)
+    }
+
+    /// 取消[start_va, end_va)中已有的映射, 部分重叠的区域被拆分, 用于munmap和MAP_FIXED
+    /// 范围中有用户态不能访问的区域时不做修改并返回false
+    pub fn unmap_range(&mut self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
         let start_vpn = start_va.floor();
         let end_vpn = end_va.ceil();
+        let overlapping = |area: &MapArea| area.get_start() < end_vpn && start_vpn < area.get_end();
+        if self
+            .areas
+            .iter()
+            .any(|area| overlapping(area) && !area.map_perm.contains(MapPermission::U))
+        {
+            return false;
+        }
 
-        self.areas.retain_mut(|area| {
-            if end_vpn <= area.get_end() && start_vpn >= area.get_start() {
-                area.unmap(&mut self.page_table);
-                false
-            } else {
-                true
+        let mut tails = Vec::new();
+        for area in self.areas.iter_mut().filter(|area| overlapping(area)) {
+            area.sync();
+            if end_vpn < area.get_end() {
+                tails.push(area.split_off(end_vpn));
             }
-        })
+            let new_end = start_vpn.max(area.get_start());
+            area.shrink_to(&mut self.page_table, new_end);
+        }
+        self.areas.retain(|area| area.get_start() < area.get_end());
+        self.areas.extend(tails);
+        // 被取消的页面可能还在其他核心的TLB中
+        remote_sfence_vma_all();
+        true
+    }
+
+    /// 将与[start_va, end_va)重叠的文件映射写回文件
+    /// 如果没有区域与该范围重叠, 返回false
+    pub fn msync(&mut self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
+        let start_vpn = start_va.floor();
+        let end_vpn = end_va.ceil();
+        let mut found = false;
+
+        for area in self.areas.iter_mut() {
+            if area.get_start() < end_vpn && start_vpn < area.get_end() {
+                area.sync();
+                found = true;
+            }
+        }
+
+        found
     }
 
     /// remove a area
@@ -368,14 +466,43 @@ impl MemorySet {
     }
 
     /// 从另外一个MemorySet构造一个新的MemorySet
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
+    /// 用户可访问的Framed区域采用写时复制, 父子进程共享物理页面并同时去掉写权限
+    /// MAP_SHARED区域直接共享物理页面, 保留写权限
+    pub fn from_existed_user(user_space: &mut MemorySet) -> MemorySet {
         let mut memory_set = Self::new_bare();
 
         memory_set.map_trampoline();
 
         for area in user_space.areas.iter() {
             // 构建新的MapArea
-            let new_area = MapArea::from_another(area);
+            let mut new_area = MapArea::from_another(area);
+
+            if area.map_type == MapType::Framed && area.map_perm.contains(MapPermission::U) {
+                // 共享物理页面, 写时复制区域父子进程的PTE都改为只读
+                // 第一次写入时触发StorePageFault, 再进行复制
+                let mut pte_flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+                if !area.shared {
+                    pte_flags -= PTEFlags::W;
+                }
+                for (vpn, page) in area.data_frames.iter() {
+                    // 持有引用期间该页面不会被换出
+                    let mut page_inner = page.inner_exclusive_access();
+                    // 被换出的页面不需要修改页表, 之后访问时再换入
+                    if let Some(ppn) = page_inner.ppn() {
+                        if !area.shared && user_space.page_table.find_vpn(*vpn) {
+                            page_inner.remap(&mut user_space.page_table, *vpn, pte_flags);
+                        }
+                        memory_set.page_table.map(*vpn, ppn, pte_flags);
+                    }
+                    page_inner.add_mapping(memory_set.token(), *vpn);
+                    drop(page_inner);
+                    new_area.data_frames.insert(*vpn, page.clone());
+                }
+                memory_set.areas.push(new_area);
+                continue;
+            }
+
+            // 例如Trap上下文, 内核会直接通过物理地址写入, 因此不能共享
             // 将新复制的MapArea放入到新的MemorySet中
             // 在页表中映射MapArea, 但是还不用复制数据，所以data为None
             memory_set.push(new_area, None);
@@ -393,6 +520,152 @@ impl MemorySet {
         memory_set
     }
 
+    /// 处理缺页异常
+    /// 1. 按需分配: 页面所在区域已经保留, 但是还没有分配物理页面
+    /// 2. 换入: 页面被换出到交换区, 或者由其他地址空间换入但是还没有映射, 写入时同时完成写时复制
+    /// 3. 写时复制: 向共享的只读页面写入
+    /// 不属于任何区域或者其他情况返回false
+    pub fn page_fault(&mut self, vpn: VirtPageNum, is_store: bool) -> bool {
+        let area = match self.areas.iter_mut().find(|area| area.contains(vpn)) {
+            Some(area) => area,
+            None => return false,
+        };
+        // PROT_NONE区域不能访问, 没有R/W/X的PTE也不是叶子PTE
+        if !area
+            .map_perm
+            .intersects(MapPermission::R | MapPermission::W | MapPermission::X)
+        {
+            return false;
+        }
+
+        match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() => {
+                is_store && !pte.is_writable() && area.copy_on_write(&mut self.page_table, vpn)
+            }
+            _ if area.swap_in(&mut self.page_table, vpn) => {
+                // 写时复制的页面换入以后是只读的, 写入时还需要复制
+                let writable = self
+                    .page_table
+                    .translate(vpn)
+                    .map_or(false, |pte| pte.is_writable());
+                !is_store || writable || area.copy_on_write(&mut self.page_table, vpn)
+            }
+            _ => area.lazy_alloc(&mut self.page_table, vpn),
+        }
+    }
+
+    /// 对用户内存[start, start + len)逐页调用f, 参数为该页中属于这段范围的部分
+    /// 内核通过物理地址直接访问, 不会触发缺页, 因此每页先完成缺页处理, 写入时还要完成写时复制
+    /// 调用f时持有页面的锁, 页面不会被其他核心换出, 因此f不能阻塞, 切片也不能保存到f之外
+    /// 范围超出用户地址空间, 或者其中有页面用户态不能按要求访问时返回false, 此前的页面已经访问过
+    pub fn access_user<F: FnMut(&mut [u8])>(
+        &mut self,
+        start: usize,
+        len: usize,
+        write: bool,
+        mut f: F,
+    ) -> bool {
+        let end = match start.checked_add(len) {
+            Some(end) if end <= USER_SPACE_END => end,
+            _ => return false,
+        };
+        let mut pos = start;
+        while pos < end {
+            let va = VirtAddr::from(pos);
+            let vpn = va.floor();
+            self.page_fault(vpn, write);
+            let ppn = match self.user_accessible_page(vpn, write) {
+                Some(ppn) => ppn,
+                None => return false,
+            };
+            let page = match self.areas.iter().find(|area| area.contains(vpn)) {
+                Some(area) => match area.data_frames.get(&vpn) {
+                    Some(page) => page,
+                    None => return false,
+                },
+                None => return false,
+            };
+            let mut page_inner = page.inner_exclusive_access();
+            // 缺页处理以后可能已经被其他核心换出, 重新处理这一页
+            if page_inner.ppn() != Some(ppn) {
+                continue;
+            }
+            // 内核通过物理地址写入, 不会设置PTE中的脏位
+            if write {
+                page_inner.set_dirty();
+            }
+            let count = (PAGE_SIZE - va.page_offset()).min(end - pos);
+            f(&mut ppn.get_bytes_array()[va.page_offset()..va.page_offset() + count]);
+            drop(page_inner);
+            pos += count;
+        }
+        true
+    }
+
+    /// 从用户内存start处读取data.len()字节
+    pub fn read_user(&mut self, start: usize, data: &mut [u8]) -> bool {
+        let mut copied = 0;
+        self.access_user(start, data.len(), false, |buffer| {
+            data[copied..copied + buffer.len()].copy_from_slice(buffer);
+            copied += buffer.len();
+        })
+    }
+
+    /// 将data写入用户内存start处, 有页面不能写入时不写入任何数据
+    pub fn write_user(&mut self, start: usize, data: &[u8]) -> bool {
+        if !self.access_user(start, data.len(), true, |_| {}) {
+            return false;
+        }
+        let mut copied = 0;
+        self.access_user(start, data.len(), true, |buffer| {
+            let len = buffer.len();
+            buffer.copy_from_slice(&data[copied..copied + len]);
+            copied += len;
+        })
+    }
+
+    /// 读取用户内存start处以0结尾的字符串, 不包括0最多max_len字节
+    pub fn read_user_str(&mut self, start: usize, max_len: usize) -> Result<String, Errno> {
+        let mut bytes = Vec::new();
+        let mut pos = start;
+        loop {
+            // 逐页检查, 字符串结尾之后的页面可能无法访问
+            let count = PAGE_SIZE - pos % PAGE_SIZE;
+            let mut result = None;
+            let accessible = self.access_user(pos, count, false, |buffer| {
+                for &ch in buffer.iter() {
+                    if ch == 0 {
+                        result = Some(Ok(()));
+                        return;
+                    }
+                    if bytes.len() == max_len {
+                        result = Some(Err(Errno::ENAMETOOLONG));
+                        return;
+                    }
+                    bytes.push(ch);
+                }
+            });
+            match result {
+                Some(Ok(())) => return Ok(bytes.iter().map(|&ch| ch as char).collect()),
+                Some(Err(errno)) => return Err(errno),
+                None if !accessible => return Err(Errno::EFAULT),
+                None => {}
+            }
+            pos += count;
+        }
+    }
+
+    /// vpn已经映射, 并且用户态可以读取或者写入时返回其物理页号
+    fn user_accessible_page(&self, vpn: VirtPageNum, write: bool) -> Option<PhysPageNum> {
+        let pte = self.page_table.translate(vpn)?;
+        let permitted = if write {
+            pte.is_writable()
+        } else {
+            pte.is_readable()
+        };
+        (pte.is_valid() && pte.flags().contains(PTEFlags::U) && permitted).then(|| pte.ppn())
+    }
+
     /// shrink the area to new_end
     pub fn shrink_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
         if let Some(area) = self
@@ -422,6 +695,13 @@ impl MemorySet {
     }
 }
 
+// 地址空间被回收时, 删除共享页面中对该地址空间的映射记录
+impl Drop for MemorySet {
+    fn drop(&mut self) {
+        self.recycle_data_pages();
+    }
+}
+
 impl MapArea {
     /// 创建一个新的区域
     /// 但是是连续的
@@ -441,12 +721,48 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            lazy: false,
+            shared: false,
+            file: None,
         }
     }
 
+    /// 创建一个按需分配的区域
+    pub fn new_lazy(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_type: MapType,
+        map_perm: MapPermission,
+    ) -> Self {
+        assert_eq!(map_type, MapType::Framed);
+        let mut map_area = Self::new(start_va, end_va, map_type, map_perm);
+        map_area.lazy = true;
+        map_area
+    }
+
+    /// 创建一个mmap区域, 按需分配
+    /// file不为None时缺页从文件中读入页面
+    pub fn new_mmap(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+        shared: bool,
+        file: Option<MmapFile>,
+    ) -> Self {
+        let mut map_area = Self::new_lazy(start_va, end_va, MapType::Framed, map_perm);
+        map_area.shared = shared;
+        map_area.file = file;
+        map_area
+    }
+
     /// map
     /// 将self映射到给定的page_table中
     pub fn map(&mut self, page_table: &mut PageTable) {
+        // 按需分配的区域在缺页时才映射
+        if self.lazy {
+            return;
+        }
+
         // 对于连续的VA，由于范围是[floor(l), ceil(r)]
         // 因此进行连续分配，如果是framed形式的map，则会为每一个page分配物理页面
         for vpn in self.vpn_range {
@@ -464,26 +780,34 @@ impl MapArea {
 
     /// map one
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        let ppn: PhysPageNum;
+        // 使用给定的权限生成页表权限
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
 
         // 确定PPN
         match self.map_type {
             MapType::Identical => {
                 // 恒等映射
                 // VA = PA
-                ppn = PhysPageNum(vpn.0);
+                // 在页表中修改PTE，真正修改映射
+                page_table.map(vpn, PhysPageNum(vpn.0), pte_flags);
             }
             MapType::Framed => {
                 let frame = frame_alloc().unwrap();
-                ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                page_table.map(vpn, frame.ppn, pte_flags);
+                // 映射完成以后才可以被换出
+                let page = self.new_page(frame, page_table.token(), vpn);
+                self.data_frames.insert(vpn, page);
             }
         }
+    }
 
-        // 使用给定的权限生成页表权限
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        // 在页表中修改PTE，真正修改映射
-        page_table.map(vpn, ppn, pte_flags);
+    /// 为vpn创建一个页面, 用户页面同时加入交换区的Clock队列
+    fn new_page(&self, frame: FrameTracker, token: usize, vpn: VirtPageNum) -> Arc<Page> {
+        let page = Arc::new(Page::new(frame, token, vpn));
+        if self.map_perm.contains(MapPermission::U) {
+            register_page(&page);
+        }
+        page
     }
 
     /// unmap one
@@ -491,16 +815,17 @@ impl MapArea {
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         match self.map_type {
             MapType::Framed => {
-                self.data_frames.remove(&vpn);
+                // 按需分配的页面可能还没有被映射
+                if let Some(page) = self.data_frames.remove(&vpn) {
+                    page.inner_exclusive_access().unmap(page_table, vpn);
+                }
             }
-            _ => {}
+            _ => page_table.unmap(vpn),
         }
-
-        page_table.unmap(vpn);
     }
 
-    /// 将data复制到page_table所对应的物理地址中
-    pub fn copy_data(&mut self, page_table: &PageTable, data: &[u8]) {
+    /// 将data复制到区域所对应的物理页面中
+    pub fn copy_data(&mut self, data: &[u8]) {
         assert_eq!(self.map_type, MapType::Framed);
         let mut start: usize = 0;
         let mut current_vpn = self.vpn_range.get_start();
@@ -510,11 +835,14 @@ impl MapArea {
         loop {
             // 一次最多只能复制一个PAGE_SIZE的数据
             let src = &data[start..len.min(start + PAGE_SIZE)];
-            let dst = &mut page_table
-                .translate(current_vpn)
+            // 映射过程中分配页面时, 之前的页面可能已经被换出
+            let ppn = self
+                .data_frames
+                .get(&current_vpn)
                 .unwrap()
-                .ppn()
-                .get_bytes_array()[..src.len()];
+                .inner_exclusive_access()
+                .swap_in();
+            let dst = &mut ppn.get_bytes_array()[..src.len()];
             dst.copy_from_slice(src);
             start += PAGE_SIZE;
             if start >= len {
@@ -529,24 +857,138 @@ impl MapArea {
         self.vpn_range.get_end().0 - self.vpn_range.get_start().0 + 1
     }
 
-    /// 检查是否被映射过
-    pub fn check_mapping(&self, page_table: &PageTable) -> bool {
-        for vpn in self.vpn_range {
-            if page_table.find_vpn(vpn) {
+    /// 判断vpn是否在当前区域内
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+
+    /// 按需分配: 第一次访问时才分配物理页面
+    /// 文件映射从文件中读入页面, MAP_SHARED映射优先使用其他映射已经读入的页面
+    pub fn lazy_alloc(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        if !self.lazy || self.data_frames.contains_key(&vpn) {
+            return false;
+        }
+
+        let file = match &self.file {
+            Some(file) => file.clone(),
+            None => {
+                self.map_one(page_table, vpn);
+                return true;
+            }
+        };
+        let page_index = vpn.0 - self.vpn_range.get_start().0;
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+
+        if self.shared {
+            if let Some(page) = file.find_shared_page(page_index) {
+                let mut page_inner = page.inner_exclusive_access();
+                let ppn = page_inner.swap_in();
+                page_table.map(vpn, ppn, pte_flags);
+                page_inner.add_mapping(page_table.token(), vpn);
+                drop(page_inner);
+                self.data_frames.insert(vpn, page);
                 return true;
             }
         }
-        false
+
+        let frame = frame_alloc().unwrap();
+        file.read_page(page_index, frame.ppn);
+        page_table.map(vpn, frame.ppn, pte_flags);
+        let page = self.new_page(frame, page_table.token(), vpn);
+        if self.shared {
+            file.insert_shared_page(page_index, &page);
+        }
+        self.data_frames.insert(vpn, page);
+        true
     }
 
-    /// 检查是否存在没被映射过的区域
-    pub fn check_unmapping(&self, page_table: &PageTable) -> bool {
-        for vpn in self.vpn_range {
-            if !page_table.find_vpn(vpn) {
-                return true;
+    /// MAP_SHARED文件映射: 将被修改过的页面写回文件
+    pub fn sync(&self) {
+        let file = match &self.file {
+            Some(file) if self.shared => file,
+            _ => return,
+        };
+
+        let start_vpn = self.vpn_range.get_start();
+        for (vpn, page) in self.data_frames.iter() {
+            // 只写回被修改过的页面, 被修改以后换出的页面需要先换入
+            let mut page_inner = page.inner_exclusive_access();
+            if page_inner.test_and_clear_dirty() {
+                let ppn = page_inner.swap_in();
+                file.write_page(vpn.0 - start_vpn.0, ppn);
             }
         }
-        false
+    }
+
+    /// 换入: 保证页面驻留在内存中, 并在page_table中重新映射
+    /// vpn还没有分配页面时返回false
+    pub fn swap_in(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let page = match self.data_frames.get(&vpn) {
+            Some(page) => page,
+            None => return false,
+        };
+
+        let mut pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        // 共享的页面只读映射, 写入时再进行写时复制
+        if !self.shared && Arc::strong_count(page) > 1 {
+            pte_flags -= PTEFlags::W;
+        }
+
+        // 持有引用期间该页面不会被换出
+        let mut page_inner = page.inner_exclusive_access();
+        let ppn = page_inner.swap_in();
+        page_table.map(vpn, ppn, pte_flags);
+
+        true
+    }
+
+    /// 写时复制
+    /// 如果页面仍然被其他地址空间共享, 复制一份新的页面; 否则直接恢复写权限
+    pub fn copy_on_write(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        if self.map_type != MapType::Framed
+            || !self.map_perm.contains(MapPermission::W)
+            || self.shared
+        {
+            return false;
+        }
+
+        let page = match self.data_frames.get(&vpn) {
+            Some(page) => page,
+            None => return false,
+        };
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        let mut page_inner = page.inner_exclusive_access();
+        // PTE有效, 页面一定驻留在内存中
+        let ppn = page_inner.ppn().unwrap();
+
+        if Arc::strong_count(page) == 1 {
+            // 其他地址空间已经不再引用该页面
+            page_inner.remap(page_table, vpn, pte_flags);
+        } else {
+            // 持有旧页面的引用, 分配新页面时旧页面不会被换出
+            let new_frame = frame_alloc().unwrap();
+            new_frame
+                .ppn
+                .get_bytes_array()
+                .copy_from_slice(ppn.get_bytes_array());
+            page_inner.remove_mapping(page_table, vpn);
+            drop(page_inner);
+            page_table.remap(vpn, new_frame.ppn, pte_flags);
+            // 旧页面的引用计数减一
+            let new_page = self.new_page(new_frame, page_table.token(), vpn);
+            self.data_frames.insert(vpn, new_page);
+        }
+
+        true
+    }
+
+    /// 删除该区域在page_table中的所有映射记录, 但是不修改页表
+    /// 用于整个地址空间被回收的时候
+    pub fn release(&mut self, page_table: &PageTable) {
+        for (vpn, page) in self.data_frames.iter() {
+            page.inner_exclusive_access()
+                .remove_mapping(page_table, *vpn);
+        }
     }
 
     /// 获取start
@@ -559,6 +1001,29 @@ impl MapArea {
         self.vpn_range.get_end()
     }
 
+    /// 访问权限
+    pub fn perm(&self) -> MapPermission {
+        self.map_perm
+    }
+
+    /// 是否是MAP_SHARED映射
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+
+    /// 文件映射的起始文件偏移, 匿名映射返回None
+    pub fn file_offset(&self) -> Option<usize> {
+        self.file.as_ref().map(|file| file.offset())
+    }
+
+    /// 驻留在内存中的页面数量, 不包括被换出的页面
+    pub fn resident_pages(&self) -> usize {
+        self.data_frames
+            .values()
+            .filter(|page| page.inner_exclusive_access().ppn().is_some())
+            .count()
+    }
+
     /// 复制另外一个MapArea
     pub fn from_another(another: &MapArea) -> Self {
         Self {
@@ -566,9 +1031,25 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            lazy: another.lazy,
+            shared: another.shared,
+            file: another.file.clone(),
         }
     }
 
+    /// 从at处拆分, self保留[start, at), 返回[at, end)
+    pub fn split_off(&mut self, at: VirtPageNum) -> MapArea {
+        let mut tail = Self::from_another(self);
+        tail.vpn_range = VPNRange::new(at, self.vpn_range.get_end());
+        tail.data_frames = self.data_frames.split_off(&at);
+        tail.file = self
+            .file
+            .as_ref()
+            .map(|file| file.advance(at.0 - self.vpn_range.get_start().0));
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), at);
+        tail
+    }
+
     /// 减少地址空间
     pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
         for vpn in VPNRange::new(new_end, self.vpn_range.get_end()) {
@@ -580,22 +1061,25 @@ impl MapArea {
 
     /// 增加
     pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
-        for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
-            self.map_one(page_table, vpn);
+        // 按需分配的区域只需要扩大范围
+        if !self.lazy {
+            for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
+                self.map_one(page_table, vpn);
+            }
         }
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
     }
 }
 
 lazy_static! {
-    /// 全局地址空间 使用ARC的共享引用与UPSafeCell的互斥访问
-    pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
-        Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
+    /// 全局地址空间 使用Arc的共享引用与SpinLock的互斥访问
+    pub static ref KERNEL_SPACE: Arc<SpinLock<MemorySet>> =
+        Arc::new(SpinLock::new(MemorySet::new_kernel()));
 }
 
 /// the Kernel token
 pub fn kernel_token() -> usize {
-    KERNEL_SPACE.exclusive_access().token()
+    KERNEL_SPACE.lock().token()
 }
 
 /// return (bottom, top) of a kernel stack in kernel space
@@ -610,7 +1094,7 @@ pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
 /// remap test in kernel_space
 #[allow(unused)]
 pub fn remap_test() {
-    let mut kernel_space = KERNEL_SPACE.exclusive_access();
+    let mut kernel_space = KERNEL_SPACE.lock();
     let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
     let mid_rodata: VirtAddr = ((srodata as usize + erodata as usize) / 2).into();
     let mid_data: VirtAddr = ((sdata as usize + edata as usize) / 2).into();
@@ -0,0 +1,112 @@
+//! 文件映射: mmap的后备文件, 以及MAP_SHARED映射之间共享的页面
+
+use super::{swap::Page, PhysPageNum};
+use crate::config::PAGE_SIZE;
+use crate::fs::Inode;
+use crate::sync::SpinLock;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use lazy_static::*;
+
+/// 共享页面表中失效项的清理阈值
+const PRUNE_THRESHOLD_MIN: usize = 256;
+
+/// 区域映射的文件
+#[derive(Clone)]
+pub struct MmapFile {
+    /// 被映射的文件
+    inode: Arc<dyn Inode>,
+    /// 区域第一个页面对应的文件偏移, 页对齐
+    offset: usize,
+}
+
+impl MmapFile {
+    /// 创建一个文件映射
+    pub fn new(inode: Arc<dyn Inode>, offset: usize) -> Self {
+        assert_eq!(offset % PAGE_SIZE, 0);
+        Self { inode, offset }
+    }
+
+    /// 区域第一个页面对应的文件偏移
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// 从第page_index个页面开始的映射, 用于拆分区域
+    pub fn advance(&self, page_index: usize) -> Self {
+        Self::new(self.inode.clone(), self.page_offset(page_index))
+    }
+
+    /// 区域中第page_index个页面对应的文件偏移
+    fn page_offset(&self, page_index: usize) -> usize {
+        self.offset + page_index * PAGE_SIZE
+    }
+
+    /// 将第page_index个页面的文件内容读入ppn, 超出文件末尾的部分为0
+    pub fn read_page(&self, page_index: usize, ppn: PhysPageNum) {
+        let bytes = ppn.get_bytes_array();
+        let len = self.inode.read_at(self.page_offset(page_index), bytes);
+        bytes[len..].fill(0);
+    }
+
+    /// 将ppn写回第page_index个页面, 不会改变文件大小
+    pub fn write_page(&self, page_index: usize, ppn: PhysPageNum) {
+        let offset = self.page_offset(page_index);
+        let size = self.inode.metadata().size;
+        if offset >= size {
+            return;
+        }
+
+        let len = PAGE_SIZE.min(size - offset);
+        self.inode.write_at(offset, &ppn.get_bytes_array()[..len]);
+    }
+
+    /// 共享页面的key: 文件的设备号, inode编号和页面在文件中的偏移
+    fn page_key(&self, page_index: usize) -> (u64, u64, usize) {
+        let metadata = self.inode.metadata();
+        (metadata.dev, metadata.ino, self.page_offset(page_index))
+    }
+
+    /// 查找其他MAP_SHARED映射已经读入的页面
+    pub fn find_shared_page(&self, page_index: usize) -> Option<Arc<Page>> {
+        SHARED_PAGES.lock().find(self.page_key(page_index))
+    }
+
+    /// 记录MAP_SHARED映射读入的页面, 之后映射同一位置时共享该页面
+    pub fn insert_shared_page(&self, page_index: usize, page: &Arc<Page>) {
+        SHARED_PAGES.lock().insert(self.page_key(page_index), page);
+    }
+}
+
+/// 文件页面到共享页面的索引
+/// 只保存弱引用, 所有映射都被取消以后页面会被回收
+struct SharedPages {
+    pages: BTreeMap<(u64, u64, usize), Weak<Page>>,
+    /// 超过该数量时清理已经被回收的页面
+    prune_threshold: usize,
+}
+
+impl SharedPages {
+    fn new() -> Self {
+        Self {
+            pages: BTreeMap::new(),
+            prune_threshold: PRUNE_THRESHOLD_MIN,
+        }
+    }
+
+    fn find(&self, key: (u64, u64, usize)) -> Option<Arc<Page>> {
+        self.pages.get(&key).and_then(|page| page.upgrade())
+    }
+
+    fn insert(&mut self, key: (u64, u64, usize), page: &Arc<Page>) {
+        if self.pages.len() >= self.prune_threshold {
+            self.pages.retain(|_, page| page.strong_count() > 0);
+            self.prune_threshold = PRUNE_THRESHOLD_MIN.max(self.pages.len() * 2);
+        }
+        self.pages.insert(key, Arc::downgrade(page));
+    }
+}
+
+lazy_static! {
+    static ref SHARED_PAGES: SpinLock<SharedPages> = SpinLock::new(SharedPages::new());
+}
@@ -5,20 +5,23 @@ mod address;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
+mod mmap;
 mod page_table;
+mod swap;
 
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
+use frame_allocator::frame_allocator_test;
+pub use frame_allocator::{
+    frame_alloc, frame_alloc_contiguous, frame_dealloc, frame_stats, FrameStats, FrameTracker,
+};
 use heap_allocator::heap_test;
 pub use memory_set::{
     kernel_stack_position, kernel_token, remap_test, MapArea, MapPermission, MapType, MemorySet,
     KERNEL_SPACE,
 };
-pub use page_table::{
-    check_map_area_mapping, check_map_area_unmapping, translated_and_write_bytes,
-    translated_byte_buffer, translated_refmut, translated_str, PageTable, PageTableEntry,
-    UserBuffer,
-};
+pub use mmap::MmapFile;
+pub use page_table::{PageTable, PageTableEntry, UserBuffer};
+pub use swap::Page;
 
 /// mm subsystem init
 pub fn init() {
@@ -27,8 +30,14 @@ pub fn init() {
     // Init Physical Frame allocator
     frame_allocator::init_frame_allocator();
     // Init Kernel address space
-    KERNEL_SPACE.exclusive_access().activate();
+    KERNEL_SPACE.lock().activate();
 
     // test
     heap_test();
+    frame_allocator_test();
+}
+
+/// 其他核心启动时切换到已经建立好的内核地址空间
+pub fn init_secondary() {
+    KERNEL_SPACE.lock().activate();
 }
@@ -1,15 +1,11 @@
-use core::ptr;
-
-use crate::config::PAGE_SIZE;
-use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use super::{
-    address::{PhysPageNum, StepByOne, VirtPageNum},
+    address::{PhysPageNum, VirtPageNum},
     frame_allocator::{frame_alloc, FrameTracker},
-    memory_set::MapArea,
     PhysAddr, VirtAddr,
 };
 
@@ -77,6 +73,21 @@ impl PageTableEntry {
     pub fn is_executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+
+    /// 判断当前PTE对应的PPN是否被访问过
+    pub fn is_accessed(&self) -> bool {
+        (self.flags() & PTEFlags::A) != PTEFlags::empty()
+    }
+
+    /// 判断当前PTE对应的PPN是否被写过
+    pub fn is_dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D) != PTEFlags::empty()
+    }
+
+    /// 硬件会在其他核心上同时设置A/D位, 修改有效的叶子PTE时需要原子操作
+    fn as_atomic(&mut self) -> &AtomicUsize {
+        unsafe { AtomicUsize::from_ptr(&mut self.bits) }
+    }
 }
 
 /// Page Table Structure
@@ -151,6 +162,17 @@ impl PageTable {
         *pte = PageTableEntry::empty();
     }
 
+    /// 修改一个已经映射的VPN的PPN与权限, 返回修改之前的PTE
+    /// VPN必须合法
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> PageTableEntry {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(pte.is_valid(), "VPN {:?} is invalid before remapping", vpn);
+        let bits = PageTableEntry::new(ppn, flags | PTEFlags::V).bits;
+        PageTableEntry {
+            bits: pte.as_atomic().swap(bits, Ordering::SeqCst),
+        }
+    }
+
     /// 从给定token中建立页表，但是实际上不会控制任何页面
     pub fn from_token(satp: usize) -> Self {
         Self {
@@ -184,6 +206,61 @@ impl PageTable {
         result
     }
 
+    /// 查找给定VPN的PTE的可变引用，但是不会创建
+    fn find_pte_mut(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+
+        for i in 0..3 {
+            let pte = &mut ppn.get_pte_array()[idxs[i]];
+            if i == 2 {
+                return Some(pte);
+            }
+
+            if !pte.is_valid() {
+                return None;
+            }
+            ppn = pte.ppn();
+        }
+
+        None
+    }
+
+    /// 清除VPN对应PTE的访问位, 返回清除之前是否被访问过
+    pub fn clear_accessed(&mut self, vpn: VirtPageNum) -> bool {
+        self.clear_flag(vpn, PTEFlags::A)
+    }
+
+    /// 清除VPN对应PTE的脏位, 返回清除之前是否被写过
+    pub fn clear_dirty(&mut self, vpn: VirtPageNum) -> bool {
+        self.clear_flag(vpn, PTEFlags::D)
+    }
+
+    /// 清除有效PTE中的A或者D位, 返回清除之前是否设置
+    fn clear_flag(&mut self, vpn: VirtPageNum, flag: PTEFlags) -> bool {
+        match self.find_pte_mut(vpn) {
+            Some(pte) if pte.is_valid() => {
+                let old = pte
+                    .as_atomic()
+                    .fetch_and(!(flag.bits as usize), Ordering::SeqCst);
+                old & flag.bits as usize != 0
+            }
+            _ => false,
+        }
+    }
+
+    /// 清空VPN对应的PTE, 返回清空之前的有效PTE
+    /// 与硬件设置脏位之间是原子的, 不会丢失已经发生的写入
+    pub fn take(&mut self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        let pte = self.find_pte_mut(vpn)?;
+        if !pte.is_valid() {
+            return None;
+        }
+        Some(PageTableEntry {
+            bits: pte.as_atomic().swap(0, Ordering::SeqCst),
+        })
+    }
+
     /// 在当前pt中找到给定vpn对应的ppn
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn).map(|pte| pte.clone())
@@ -217,128 +294,6 @@ impl PageTable {
     }
 }
 
-/// 使用给定token建立页表，然后在给定页表中找到[ptr, ptr + len)
-/// 所对应的物理地址，以Byte数组的形式返回
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
-    let mut start = ptr as usize;
-    let end = start + len;
-
-    let mut v = Vec::new();
-
-    while start < end {
-        // 计算起点VA
-        let start_va = VirtAddr::from(start);
-        // 计算start_va所在的VPN
-        let mut vpn = start_va.floor();
-        // 找到该VPN真实对应的PPN
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        vpn.step();
-        // 计算终止VA
-        let mut end_va: VirtAddr = vpn.into();
-        // 判断一下end_va 和 end谁小，end_va取最小
-        end_va = end_va.min(VirtAddr::from(end));
-        // page_offset获得页内偏移
-        // 这里有两种情况
-        // 1. end_va < end：说明还有一页，page_offset是0
-        // 2. end_va = end：取页内偏移
-        if end_va.page_offset() == 0 {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
-        }
-        start = end_va.into();
-    }
-
-    v
-}
-
-/// 将给定 data 长度为len的数据写入ptr指向的va中
-pub fn translated_and_write_bytes(token: usize, ptr: *const u8, data: *const u8, len: usize) {
-    let page_table = PageTable::from_token(token);
-    let mut start = ptr as usize;
-    let end = start + len;
-
-    let mut write_cnt = 0;
-
-    while start < end {
-        let start_va: VirtAddr = VirtAddr::from(start);
-        let mut vpn: VirtPageNum = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        vpn.step();
-
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-
-        let address_array = ppn.get_bytes_array();
-
-        unsafe {
-            if end_va.page_offset() == 0 {
-                for i in start_va.page_offset()..PAGE_SIZE {
-                    let addr = address_array.as_mut_ptr().add(i);
-                    ptr::write_volatile(addr, *data.add(write_cnt));
-                    write_cnt += 1;
-                }
-            } else {
-                for i in start_va.page_offset()..end_va.page_offset() {
-                    let addr = address_array.as_mut_ptr().add(i);
-                    ptr::write_volatile(addr, *data.add(write_cnt));
-                    write_cnt += 1;
-                }
-            }
-        }
-        start = end_va.into();
-    }
-}
-
-/// 检查一段内存是否已经被map了
-pub fn check_map_area_mapping(token: usize, map_area: MapArea) -> bool {
-    let page_table = PageTable::from_token(token);
-
-    map_area.check_mapping(&page_table)
-}
-
-/// 检查一段内存中是否存在未映射的区域
-pub fn check_map_area_unmapping(token: usize, map_area: MapArea) -> bool {
-    let page_table = PageTable::from_token(token);
-
-    map_area.check_unmapping(&page_table)
-}
-
-/// 逐字节转换为String, 可能跨页
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
-    let mut string = String::new();
-    let mut va = ptr as usize;
-
-    loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-
-        if ch == 0 {
-            break;
-        } else {
-            string.push(ch as char);
-            va += 1;
-        }
-    }
-
-    string
-}
-
-/// 将一个Byte指针翻译为物理地址
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
-}
-
 /// 用户态与内核态中的用户缓冲区
 pub struct UserBuffer {
     /// 缓冲区数组列表
@@ -0,0 +1,327 @@
+//! Swap: 物理页面不足时, 将用户页面换出到块设备上的交换区
+//! 使用Clock(二次机会)算法选择被换出的页面
+
+use super::page_table::PTEFlags;
+use super::{frame_alloc, FrameTracker, PageTable, PageTableEntry, PhysPageNum, VirtPageNum};
+use crate::config::{PAGE_SIZE, SWAP_BLOCK_START, SWAP_SLOT_NUM};
+use crate::drivers::BLOCK_DEVICE;
+use crate::sbi::remote_sfence_vma_all;
+use crate::sync::{SpinLock, SpinLockGuard};
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// 块设备的块大小
+const BLOCK_SZ: usize = 512;
+/// 一个页面在交换区中占用的块数
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_SZ;
+/// Clock队列中失效项的清理阈值
+const PRUNE_THRESHOLD_MIN: usize = 1024;
+
+/// 映射到地址空间中的一个页面
+/// 可能驻留在内存中, 也可能被换出到交换区
+pub struct Page {
+    inner: SpinLock<PageInner>,
+}
+
+/// Page的可变部分
+/// mappings中每一个PTE只在持有该页面的锁时修改, 换出时会修改其他地址空间的页表,
+/// 因此不需要获取这些进程的锁; 硬件同时设置的A/D位通过原子操作处理
+pub struct PageInner {
+    /// 驻留在内存中的物理页面, 被换出时为None
+    frame: Option<FrameTracker>,
+    /// 交换区中的位置
+    /// 换入以后仍然保留, 页面没有被修改时换出不需要重新写回
+    swap_slot: Option<usize>,
+    /// 交换区中的副本是否已经过期
+    dirty: bool,
+    /// 是否与映射的文件中的内容不一致, 写回文件以后清除
+    file_dirty: bool,
+    /// 所有引用该页面的(token, vpn), 换出时需要修改这些页表
+    /// 地址空间回收页表之前会删除自己的映射记录
+    mappings: Vec<(usize, VirtPageNum)>,
+    /// 固定在内存中的次数, 不为0时不会被换出
+    pins: usize,
+}
+
+impl Page {
+    /// 创建一个驻留在内存中的页面, 同时记录第一个映射
+    pub fn new(frame: FrameTracker, token: usize, vpn: VirtPageNum) -> Self {
+        Self {
+            inner: SpinLock::new(PageInner {
+                frame: Some(frame),
+                swap_slot: None,
+                dirty: false,
+                file_dirty: false,
+                mappings: vec![(token, vpn)],
+                pins: 0,
+            }),
+        }
+    }
+
+    /// 获取可变引用
+    /// 持有引用期间该页面不会被换出
+    pub fn inner_exclusive_access(&self) -> SpinLockGuard<'_, PageInner> {
+        self.inner.lock()
+    }
+
+    /// 换入并固定在内存中, 直到对应的unpin之前物理页号不会改变
+    pub fn pin(&self) -> PhysPageNum {
+        let mut inner = self.inner.lock();
+        inner.pins += 1;
+        inner.swap_in()
+    }
+
+    /// 取消一次pin
+    pub fn unpin(&self) {
+        self.inner.lock().pins -= 1;
+    }
+}
+
+impl PageInner {
+    /// 驻留在内存中的物理页号
+    pub fn ppn(&self) -> Option<PhysPageNum> {
+        self.frame.as_ref().map(|frame| frame.ppn)
+    }
+
+    /// 保证页面驻留在内存中, 必要时从交换区读回
+    pub fn swap_in(&mut self) -> PhysPageNum {
+        if let Some(ppn) = self.ppn() {
+            return ppn;
+        }
+
+        let frame = frame_alloc().unwrap();
+        read_slot(self.swap_slot.unwrap(), frame.ppn);
+        let ppn = frame.ppn;
+        self.frame = Some(frame);
+        // 与交换区中的副本一致
+        self.dirty = false;
+        ppn
+    }
+
+    /// 页面被修改, 例如内核通过物理地址写入, 不会设置PTE中的脏位
+    pub fn set_dirty(&mut self) {
+        self.dirty = true;
+        self.file_dirty = true;
+    }
+
+    /// 页面是否与文件中的内容不一致, 同时清除所有映射的脏位, 用于写回文件
+    /// 清除以后刷新TLB, 之后的写入会重新设置脏位
+    pub fn test_and_clear_dirty(&mut self) -> bool {
+        let mut cleared = false;
+        for &(token, vpn) in self.mappings.iter() {
+            cleared |= PageTable::from_token(token).clear_dirty(vpn);
+        }
+        if cleared {
+            remote_sfence_vma_all();
+            // 交换区中的副本同样过期
+            self.dirty = true;
+        }
+        core::mem::take(&mut self.file_dirty) || cleared
+    }
+
+    /// 增加一个映射
+    pub fn add_mapping(&mut self, token: usize, vpn: VirtPageNum) {
+        self.mappings.push((token, vpn));
+    }
+
+    /// 删除一个映射
+    pub fn remove_mapping(&mut self, page_table: &PageTable, vpn: VirtPageNum) {
+        self.save_dirty(page_table, vpn);
+        let token = page_table.token();
+        self.mappings.retain(|&(t, v)| !(t == token && v == vpn));
+    }
+
+    /// 删除一个映射并清空对应的PTE, 被换出的页面已经没有有效的PTE
+    pub fn unmap(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        if let Some(pte) = page_table.take(vpn) {
+            self.save_pte_dirty(pte);
+        }
+        let token = page_table.token();
+        self.mappings.retain(|&(t, v)| !(t == token && v == vpn));
+    }
+
+    /// 修改一个映射的权限, 页面必须驻留在内存中
+    pub fn remap(&mut self, page_table: &mut PageTable, vpn: VirtPageNum, flags: PTEFlags) {
+        let old = page_table.remap(vpn, self.ppn().unwrap(), flags);
+        self.save_pte_dirty(old);
+    }
+
+    /// 页表项即将被修改, 先保存其中的脏位
+    fn save_dirty(&mut self, page_table: &PageTable, vpn: VirtPageNum) {
+        if let Some(pte) = page_table.translate(vpn) {
+            if pte.is_valid() {
+                self.save_pte_dirty(pte);
+            }
+        }
+    }
+
+    /// 保存已经被清除或者修改的PTE中的脏位
+    fn save_pte_dirty(&mut self, pte: PageTableEntry) {
+        if pte.is_dirty() {
+            self.set_dirty();
+        }
+    }
+
+    /// 是否被访问过, 同时清除所有映射的访问位
+    fn test_and_clear_accessed(&self) -> bool {
+        let mut accessed = false;
+        for &(token, vpn) in self.mappings.iter() {
+            accessed |= PageTable::from_token(token).clear_accessed(vpn);
+        }
+        accessed
+    }
+
+    /// 换出到slot: 清除所有映射并刷新TLB, 然后必要时写回交换区并释放物理页面
+    fn swap_out(&mut self, slot: usize) {
+        // 刷新TLB以后不会再有写入, 此时收集到的脏位才是完整的
+        let mut dirty = false;
+        for &(token, vpn) in self.mappings.iter() {
+            if let Some(pte) = PageTable::from_token(token).take(vpn) {
+                if pte.is_dirty() {
+                    dirty = true;
+                }
+            }
+        }
+        // 这些地址空间可能正在其他核心上运行
+        remote_sfence_vma_all();
+        if dirty {
+            self.set_dirty();
+        }
+
+        let ppn = self.ppn().unwrap();
+        if self.swap_slot != Some(slot) || self.dirty {
+            write_slot(slot, ppn);
+        }
+        self.swap_slot = Some(slot);
+        self.dirty = false;
+
+        // FrameTracker被回收
+        self.frame = None;
+    }
+}
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        // 最后一个引用可能在换出的过程中被释放, 因此不能使用SWAP_MANAGER
+        if let Some(slot) = self.inner.lock().swap_slot {
+            FREE_SLOTS.lock().push(slot);
+        }
+    }
+}
+
+/// 交换区管理器
+struct SwapManager {
+    /// 可以被换出的页面, 作为Clock算法的环形队列
+    pages: Vec<Weak<Page>>,
+    /// Clock指针
+    hand: usize,
+    /// 超过该长度时清理已经被回收的页面
+    prune_threshold: usize,
+}
+
+impl SwapManager {
+    fn new() -> Self {
+        Self {
+            pages: Vec::new(),
+            hand: 0,
+            prune_threshold: PRUNE_THRESHOLD_MIN,
+        }
+    }
+
+    fn register(&mut self, page: &Arc<Page>) {
+        if self.pages.len() >= self.prune_threshold {
+            self.pages.retain(|page| page.strong_count() > 0);
+            self.hand = 0;
+            self.prune_threshold = PRUNE_THRESHOLD_MIN.max(self.pages.len() * 2);
+        }
+        self.pages.push(Arc::downgrade(page));
+    }
+
+    /// 使用Clock算法换出一个页面
+    fn swap_out(&mut self) -> bool {
+        // 第一圈清除访问位, 第二圈一定可以找到没有被访问过的页面
+        let mut budget = self.pages.len() * 2;
+
+        while budget > 0 && !self.pages.is_empty() {
+            budget -= 1;
+            if self.hand >= self.pages.len() {
+                self.hand = 0;
+            }
+
+            let page = match self.pages[self.hand].upgrade() {
+                Some(page) => page,
+                None => {
+                    // 页面已经被回收
+                    self.pages.remove(self.hand);
+                    continue;
+                }
+            };
+            self.hand += 1;
+
+            // 正在被使用的页面不能换出
+            let mut inner = match page.inner.try_lock() {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            if inner.frame.is_none() || inner.pins > 0 || inner.test_and_clear_accessed() {
+                continue;
+            }
+
+            let slot = match inner.swap_slot {
+                Some(slot) => slot,
+                None => match FREE_SLOTS.lock().pop() {
+                    Some(slot) => slot,
+                    // 交换区已满
+                    None => return false,
+                },
+            };
+
+            inner.swap_out(slot);
+            return true;
+        }
+
+        false
+    }
+}
+
+lazy_static! {
+    static ref SWAP_MANAGER: SpinLock<SwapManager> =
+        SpinLock::new(SwapManager::new());
+    /// 交换区中空闲的slot
+    static ref FREE_SLOTS: SpinLock<Vec<usize>> =
+        SpinLock::new((0..SWAP_SLOT_NUM).rev().collect());
+}
+
+/// 将用户页面加入Clock队列, 之后可以被换出
+pub fn register_page(page: &Arc<Page>) {
+    SWAP_MANAGER.lock().register(page);
+}
+
+/// 换出一个页面, 没有页面可以换出时返回false
+pub fn swap_out() -> bool {
+    SWAP_MANAGER.lock().swap_out()
+}
+
+/// slot中第一个块的块号
+fn slot_block_id(slot: usize) -> usize {
+    SWAP_BLOCK_START + slot * BLOCKS_PER_PAGE
+}
+
+/// 将ppn对应页面写入交换区
+fn write_slot(slot: usize, ppn: PhysPageNum) {
+    let bytes = ppn.get_bytes_array();
+    for (i, block) in bytes.chunks(BLOCK_SZ).enumerate() {
+        BLOCK_DEVICE.write_block(slot_block_id(slot) + i, block);
+    }
+}
+
+/// 从交换区读取页面到ppn
+fn read_slot(slot: usize, ppn: PhysPageNum) {
+    let bytes = ppn.get_bytes_array();
+    for (i, block) in bytes.chunks_mut(BLOCK_SZ).enumerate() {
+        BLOCK_DEVICE.read_block(slot_block_id(slot) + i, block);
+    }
+}
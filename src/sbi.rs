@@ -7,6 +7,11 @@ const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
 const SBI_SHUTDOWN: usize = 8;
 
+const SBI_EXT_RFENCE: usize = 0x52464E43;
+const SBI_REMOTE_SFENCE_VMA: usize = 1;
+const SBI_EXT_HSM: usize = 0x48534D;
+const SBI_HART_START: usize = 0;
+
 #[inline(always)]
 /// General Sbi Call
 fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
@@ -24,6 +29,32 @@ fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
+#[inline(always)]
+/// 带有扩展号和功能号的Sbi Call, 返回错误码
+fn sbi_ext_call(
+    eid: usize,
+    fid: usize,
+    arg0: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+) -> isize {
+    let mut error;
+
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") arg0 => error,
+            in("x11") arg1,
+            in("x12") arg2,
+            in("x13") arg3,
+            in("x16") fid,
+            in("x17") eid,
+        );
+    }
+    error
+}
+
 /// sbi 设置 timer
 pub fn set_timer(timer: usize) {
     sbi_call(SBI_SET_TIMER, timer, 0, 0);
@@ -44,3 +75,22 @@ pub fn shutdown() -> ! {
 pub fn console_getchar() -> usize {
     sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
 }
+
+/// sbi HSM: 启动hartid对应的核心, 从start_addr开始执行, a1为opaque
+/// 核心不存在或者已经启动时返回false
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> bool {
+    sbi_ext_call(SBI_EXT_HSM, SBI_HART_START, hartid, start_addr, opaque, 0) == 0
+}
+
+/// sbi RFENCE: 刷新所有核心的TLB
+pub fn remote_sfence_vma_all() {
+    // hart_mask_base为-1时表示所有核心, size为-1时表示整个地址空间
+    sbi_ext_call(
+        SBI_EXT_RFENCE,
+        SBI_REMOTE_SFENCE_VMA,
+        0,
+        usize::MAX,
+        0,
+        usize::MAX,
+    );
+}
@@ -0,0 +1,79 @@
+//! Condvar: 通过系统调用提供给用户程序的条件变量
+
+use super::{SpinLock, UserMutex, WaitQueue};
+use alloc::collections::BTreeSet;
+
+/// 条件变量
+/// 每个等待者取一个递增的编号, signal按照编号顺序允许等待者返回
+pub struct Condvar {
+    state: SpinLock<CondvarState>,
+    /// 等待signal的线程
+    wait_queue: WaitQueue,
+}
+
+struct CondvarState {
+    /// 下一个等待者的编号
+    next_ticket: usize,
+    /// 编号小于该值的等待者已经被signal
+    signaled: usize,
+    /// 被打断而放弃等待的编号, signal时跳过
+    abandoned: BTreeSet<usize>,
+}
+
+impl Condvar {
+    /// 创建一个没有等待者的条件变量
+    pub fn new() -> Self {
+        Self {
+            state: SpinLock::new(CondvarState {
+                next_ticket: 0,
+                signaled: 0,
+                abandoned: BTreeSet::new(),
+            }),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    /// 唤醒最早等待的一个线程, 没有等待者时什么也不做
+    pub fn signal(&self) {
+        let mut state = self.state.lock();
+        loop {
+            if state.signaled == state.next_ticket {
+                return;
+            }
+            let ticket = state.signaled;
+            state.signaled += 1;
+            if !state.abandoned.remove(&ticket) {
+                break;
+            }
+        }
+        drop(state);
+        // 只有编号满足条件的等待者会返回, 其他的继续阻塞
+        self.wait_queue.wake_all();
+    }
+
+    /// 释放mutex并等待signal, 返回之前重新获取mutex
+    /// 释放mutex以后才发生的signal也不会丢失
+    /// interrupted返回true或者被信号打断时放弃等待, 此时不再获取mutex并返回false
+    pub fn wait<F: Fn() -> bool>(&self, mutex: &UserMutex, interrupted: F) -> bool {
+        let ticket = {
+            let mut state = self.state.lock();
+            state.next_ticket += 1;
+            state.next_ticket - 1
+        };
+        mutex.unlock();
+        let mut signaled = false;
+        self.wait_queue.wait_until_interruptible(|| {
+            signaled = ticket < self.state.lock().signaled;
+            signaled || interrupted()
+        });
+        if !signaled {
+            let mut state = self.state.lock();
+            // 放弃等待之前可能已经被signal, 此时照常获取mutex
+            signaled = ticket < state.signaled;
+            if !signaled {
+                state.abandoned.insert(ticket);
+            }
+        }
+        signaled && mutex.lock(interrupted)
+    }
+}
@@ -0,0 +1,125 @@
+//! Deadlock detection: 用银行家算法检查用户互斥锁和信号量的请求
+//! 记录每个线程已经获得和正在请求的资源, 请求以后找不到让所有线程完成的顺序时拒绝该请求
+//! 信号量可以由没有获得它的线程释放, 此时检测可能过于保守
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// 死锁检测中的一类资源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    /// 互斥锁, 通过id索引
+    Mutex(usize),
+    /// 信号量, 通过id索引
+    Semaphore(usize),
+}
+
+/// 每个线程持有或者请求的每类资源的数量
+type ResourceTable = BTreeMap<usize, BTreeMap<Resource, usize>>;
+
+/// 进程内的死锁检测
+#[derive(Default)]
+pub struct DeadlockDetector {
+    /// 启用时拒绝可能导致死锁的请求, 关闭时只记录
+    pub enabled: bool,
+    /// 可用的资源数量
+    available: BTreeMap<Resource, usize>,
+    /// 每个线程已经获得的资源
+    allocation: ResourceTable,
+    /// 每个线程正在等待的资源
+    need: ResourceTable,
+}
+
+/// table[tid][res] += 1
+fn table_inc(table: &mut ResourceTable, tid: usize, res: Resource) {
+    *table.entry(tid).or_default().entry(res).or_insert(0) += 1;
+}
+
+/// table[tid][res] -= 1, 为0时删除, 返回原来是否大于0
+fn table_dec(table: &mut ResourceTable, tid: usize, res: Resource) -> bool {
+    let row = match table.get_mut(&tid) {
+        Some(row) => row,
+        None => return false,
+    };
+    let count = match row.get_mut(&res) {
+        Some(count) => count,
+        None => return false,
+    };
+    *count -= 1;
+    if *count == 0 {
+        row.remove(&res);
+        if row.is_empty() {
+            table.remove(&tid);
+        }
+    }
+    true
+}
+
+impl DeadlockDetector {
+    /// 创建资源时记录可用的数量
+    pub fn add_resource(&mut self, res: Resource, count: usize) {
+        self.available.insert(res, count);
+    }
+
+    /// 线程tid请求一个res
+    /// 启用检测并且请求以后不再安全时返回false, 此时不记录该请求
+    pub fn request(&mut self, tid: usize, res: Resource) -> bool {
+        table_inc(&mut self.need, tid, res);
+        if self.enabled && !self.is_safe() {
+            table_dec(&mut self.need, tid, res);
+            return false;
+        }
+        true
+    }
+
+    /// 线程tid获得了请求的res
+    pub fn acquire(&mut self, tid: usize, res: Resource) {
+        table_dec(&mut self.need, tid, res);
+        table_inc(&mut self.allocation, tid, res);
+        if let Some(available) = self.available.get_mut(&res) {
+            *available = available.saturating_sub(1);
+        }
+    }
+
+    /// 线程tid释放了一个res
+    pub fn release(&mut self, tid: usize, res: Resource) {
+        table_dec(&mut self.allocation, tid, res);
+        *self.available.entry(res).or_insert(0) += 1;
+    }
+
+    /// 线程被回收以后tid可能复用, 删除它的记录
+    /// 它持有的资源不会再被释放, 因此不归还
+    pub fn remove_thread(&mut self, tid: usize) {
+        self.allocation.remove(&tid);
+        self.need.remove(&tid);
+    }
+
+    /// 是否存在一个顺序, 每个线程的请求都可以满足, 完成以后释放持有的资源
+    fn is_safe(&self) -> bool {
+        let mut work = self.available.clone();
+        let mut unfinished: Vec<usize> = self
+            .need
+            .keys()
+            .chain(self.allocation.keys())
+            .copied()
+            .collect();
+        unfinished.sort_unstable();
+        unfinished.dedup();
+
+        let satisfied = |work: &BTreeMap<Resource, usize>, tid: &usize| {
+            self.need.get(tid).map_or(true, |row| {
+                row.iter()
+                    .all(|(res, count)| work.get(res).copied().unwrap_or(0) >= *count)
+            })
+        };
+        while let Some(idx) = unfinished.iter().position(|tid| satisfied(&work, tid)) {
+            let tid = unfinished.swap_remove(idx);
+            if let Some(row) = self.allocation.get(&tid) {
+                for (res, count) in row {
+                    *work.entry(*res).or_insert(0) += count;
+                }
+            }
+        }
+        unfinished.is_empty()
+    }
+}
@@ -0,0 +1,150 @@
+//! Futex: 以用户内存中一个u32为键的等待队列
+//! 用户程序没有竞争时只使用原子操作, 有竞争时才通过系统调用阻塞和唤醒
+
+use super::SpinLock;
+use crate::errno::Errno;
+use crate::task::{
+    block_current_and_run_next, current_task, wakeup_task, TaskControlBlock, TaskStatus,
+};
+use crate::timer::add_timer;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// 等待者被移出队列的原因
+#[derive(Copy, Clone, PartialEq)]
+enum FutexWake {
+    /// 还在队列中
+    Waiting,
+    /// 被futex_wake唤醒
+    Woken,
+    /// 到达超时时间
+    Timeout,
+}
+
+/// 阻塞在futex上的线程
+struct FutexWaiter {
+    task: Arc<TaskControlBlock>,
+    /// 只有将它移出队列的一方可以在持有表锁时修改, 并负责唤醒
+    wake: SpinLock<FutexWake>,
+}
+
+/// futex的键
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum FutexKey {
+    /// 只在进程内使用的futex, 以(pid, 虚拟地址)为键
+    Private(usize, usize),
+    /// MAP_SHARED映射中的futex, 以物理地址为键, 可以在进程之间使用
+    /// 有线程等待期间页面需要固定在内存中, 否则换出以后物理地址会改变
+    Shared(usize),
+}
+
+type FutexTable = BTreeMap<FutexKey, VecDeque<Arc<FutexWaiter>>>;
+
+lazy_static! {
+    /// 键 -> 等待者, 队列为空时删除
+    static ref FUTEX_TABLE: SpinLock<FutexTable> = SpinLock::new(BTreeMap::new());
+}
+
+/// 将等待者移出key对应的队列, 已经被其他原因移出时返回false
+fn remove_waiter(table: &mut FutexTable, key: FutexKey, waiter: &Arc<FutexWaiter>) -> bool {
+    let queue = match table.get_mut(&key) {
+        Some(queue) => queue,
+        None => return false,
+    };
+    let idx = match queue.iter().position(|other| Arc::ptr_eq(other, waiter)) {
+        Some(idx) => idx,
+        None => return false,
+    };
+    queue.remove(idx);
+    if queue.is_empty() {
+        table.remove(&key);
+    }
+    true
+}
+
+/// 唤醒已经移出队列并设置了唤醒原因的等待者
+fn wake_waiter(waiter: &FutexWaiter) {
+    wakeup_task(waiter.task.clone());
+}
+
+/// 在key上阻塞当前线程, 直到被futex_wake唤醒, 时钟计数到达deadline或者被信号打断
+/// check在持有表锁时执行, 例如比较用户内存中的值, 返回错误时不阻塞
+/// 因此在check之后修改值并调用futex_wake的线程不会错过这次等待
+pub fn futex_wait<F: FnOnce() -> Result<(), Errno>>(
+    key: FutexKey,
+    check: F,
+    deadline: Option<usize>,
+) -> Result<(), Errno> {
+    let task = current_task().unwrap();
+    let waiter = Arc::new(FutexWaiter {
+        task: task.clone(),
+        wake: SpinLock::new(FutexWake::Waiting),
+    });
+
+    let mut table = FUTEX_TABLE.lock();
+    check()?;
+    // 在任务的锁中检查interrupted, send_signal的唤醒不会丢失
+    let mut inner = task.inner_exclusive_access();
+    if inner.interrupted {
+        return Err(Errno::EINTR);
+    }
+    // 先标记为Blocked再释放表锁, 在切换之前被唤醒时相当于一次yield
+    inner.task_status = TaskStatus::Blocked;
+    inner.interruptible = true;
+    drop(inner);
+    table.entry(key).or_default().push_back(waiter.clone());
+    drop(table);
+    drop(task);
+
+    let timer = deadline.map(|deadline| {
+        let waiter = waiter.clone();
+        add_timer(deadline, move || {
+            let mut table = FUTEX_TABLE.lock();
+            if remove_waiter(&mut table, key, &waiter) {
+                *waiter.wake.lock() = FutexWake::Timeout;
+                drop(table);
+                wake_waiter(&waiter);
+            }
+        })
+    });
+    block_current_and_run_next();
+    if let Some(timer) = timer {
+        timer.cancel();
+    }
+
+    // 被信号唤醒时仍然在队列中
+    let mut table = FUTEX_TABLE.lock();
+    if remove_waiter(&mut table, key, &waiter) {
+        return Err(Errno::EINTR);
+    }
+    let wake = *waiter.wake.lock();
+    match wake {
+        FutexWake::Timeout => Err(Errno::ETIMEDOUT),
+        FutexWake::Waiting | FutexWake::Woken => Ok(()),
+    }
+}
+
+/// 按等待顺序唤醒key上最多count个线程, 返回唤醒的数量
+pub fn futex_wake(key: FutexKey, count: usize) -> usize {
+    let mut table = FUTEX_TABLE.lock();
+    let queue = match table.get_mut(&key) {
+        Some(queue) => queue,
+        None => return 0,
+    };
+    let count = count.min(queue.len());
+    let woken: Vec<_> = queue.drain(..count).collect();
+    if queue.is_empty() {
+        table.remove(&key);
+    }
+    for waiter in woken.iter() {
+        *waiter.wake.lock() = FutexWake::Woken;
+    }
+    drop(table);
+
+    for waiter in woken.iter() {
+        wake_waiter(waiter);
+    }
+    woken.len()
+}
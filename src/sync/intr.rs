@@ -0,0 +1,57 @@
+//! 嵌套的关中断: 持有自旋锁期间当前核心不会响应中断
+
+use crate::config::MAX_HART_NUM;
+use crate::task::hart_id;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use riscv::register::sstatus;
+
+/// 每个核心的关中断状态
+/// 只会被所在核心在关中断时访问, 因此Relaxed即可
+struct IntrState {
+    /// push_off的嵌套深度
+    depth: AtomicUsize,
+    /// 第一次push_off之前是否开中断
+    enabled: AtomicBool,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const INTR_STATE_INIT: IntrState = IntrState {
+    depth: AtomicUsize::new(0),
+    enabled: AtomicBool::new(false),
+};
+
+static INTR_STATES: [IntrState; MAX_HART_NUM] = [INTR_STATE_INIT; MAX_HART_NUM];
+
+/// 关中断, 并记录嵌套深度
+pub fn push_off() {
+    let enabled = sstatus::read().sie();
+    unsafe {
+        sstatus::clear_sie();
+    }
+
+    let state = &INTR_STATES[hart_id()];
+    if state.depth.load(Ordering::Relaxed) == 0 {
+        state.enabled.store(enabled, Ordering::Relaxed);
+    }
+    state.depth.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 与push_off配对, 最外层恢复之前的中断状态
+pub fn pop_off() {
+    let state = &INTR_STATES[hart_id()];
+    let depth = state.depth.load(Ordering::Relaxed);
+    assert!(depth > 0, "pop_off without push_off");
+    state.depth.store(depth - 1, Ordering::Relaxed);
+
+    if depth == 1 && state.enabled.load(Ordering::Relaxed) {
+        unsafe {
+            sstatus::set_sie();
+        }
+    }
+}
+
+/// 当前核心持有的自旋锁数量
+#[cfg(feature = "lock-debug")]
+pub fn intr_depth() -> usize {
+    INTR_STATES[hart_id()].depth.load(Ordering::Relaxed)
+}
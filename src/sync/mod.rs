@@ -1,5 +1,23 @@
 //! Synchronization and interior mutability primitives;
+//! 启用lock-debug feature时检查重复加锁和死锁
 
-mod up;
+mod condvar;
+mod deadlock;
+mod futex;
+mod intr;
+mod mutex;
+mod semaphore;
+mod spin;
+mod user_mutex;
+mod wait_queue;
 
-pub use up::UPSafeCell;
+pub use condvar::Condvar;
+pub use deadlock::{DeadlockDetector, Resource};
+pub use futex::{futex_wait, futex_wake, FutexKey};
+#[cfg(feature = "lock-debug")]
+pub use intr::intr_depth;
+pub use mutex::{Mutex, MutexGuard};
+pub use semaphore::Semaphore;
+pub use spin::{SpinLock, SpinLockGuard};
+pub use user_mutex::UserMutex;
+pub use wait_queue::WaitQueue;
@@ -0,0 +1,130 @@
+//! Sleeping mutex: 锁被占用时阻塞当前任务, 而不是自旋等待
+
+use super::{SpinLock, WaitQueue};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "lock-debug")]
+use super::intr_depth;
+#[cfg(feature = "lock-debug")]
+use crate::task::current_task;
+#[cfg(feature = "lock-debug")]
+use alloc::sync::Arc;
+#[cfg(feature = "lock-debug")]
+use core::panic::Location;
+
+/// 可睡眠的互斥锁
+/// 适合持有时间较长的数据, 例如需要访问块设备的文件
+pub struct Mutex<T> {
+    state: SpinLock<MutexState>,
+    /// 等待该锁的任务
+    wait_queue: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+/// Mutex的状态
+struct MutexState {
+    locked: bool,
+    /// 持有者, 同一进程的线程pid相同, 因此记录线程的地址
+    #[cfg(feature = "lock-debug")]
+    owner: Option<usize>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+unsafe impl<T: Send> Send for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// 创建一个未上锁的互斥锁
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: SpinLock::new(MutexState {
+                locked: false,
+                #[cfg(feature = "lock-debug")]
+                owner: None,
+            }),
+            wait_queue: WaitQueue::new(),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// 获取锁, 锁被占用时阻塞当前任务
+    /// 不能在持有自旋锁时调用
+    #[track_caller]
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        #[cfg(feature = "lock-debug")]
+        let owner = self.check();
+
+        self.wait_queue.wait_until(|| {
+            let mut state = self.state.lock();
+            if state.locked {
+                return false;
+            }
+            state.locked = true;
+            #[cfg(feature = "lock-debug")]
+            {
+                state.owner = owner;
+            }
+            true
+        });
+
+        MutexGuard { mutex: self }
+    }
+
+    /// 同一个任务重复获取, 或者持有自旋锁时睡眠
+    #[cfg(feature = "lock-debug")]
+    #[track_caller]
+    fn check(&self) -> Option<usize> {
+        let owner = current_task().map(|task| Arc::as_ptr(&task) as usize);
+        if owner.is_some() && intr_depth() > 0 {
+            panic!(
+                "Mutex: locked at {} while holding {} spin locks",
+                Location::caller(),
+                intr_depth()
+            );
+        }
+        if owner.is_some() && self.state.lock().owner == owner {
+            panic!(
+                "Mutex: task {:#x} locks again at {}",
+                owner.unwrap(),
+                Location::caller()
+            );
+        }
+        owner
+    }
+
+    fn unlock(&self) {
+        let mut state = self.state.lock();
+        state.locked = false;
+        #[cfg(feature = "lock-debug")]
+        {
+            state.owner = None;
+        }
+        drop(state);
+        self.wait_queue.wake_one();
+    }
+}
+
+/// Mutex的RAII guard, 离开作用域时释放锁并唤醒一个等待的任务
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
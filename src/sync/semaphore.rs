@@ -0,0 +1,42 @@
+//! Semaphore: 通过系统调用提供给用户程序的信号量
+
+use super::{SpinLock, WaitQueue};
+
+/// 计数信号量
+pub struct Semaphore {
+    /// 可用的资源数量
+    count: SpinLock<usize>,
+    /// 等待资源的线程
+    wait_queue: WaitQueue,
+}
+
+impl Semaphore {
+    /// 创建一个有count个资源的信号量
+    pub fn new(count: usize) -> Self {
+        Self {
+            count: SpinLock::new(count),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    /// 释放一个资源并唤醒一个等待的线程
+    pub fn up(&self) {
+        *self.count.lock() += 1;
+        self.wait_queue.wake_one();
+    }
+
+    /// 获取一个资源, 没有可用的资源时阻塞
+    /// interrupted返回true或者被信号打断时放弃等待, 此时返回false
+    pub fn down<F: Fn() -> bool>(&self, interrupted: F) -> bool {
+        let mut acquired = false;
+        self.wait_queue.wait_until_interruptible(|| {
+            let mut count = self.count.lock();
+            if *count > 0 {
+                *count -= 1;
+                acquired = true;
+            }
+            acquired || interrupted()
+        });
+        acquired
+    }
+}
@@ -0,0 +1,170 @@
+//! Spin lock: 多核之间互斥访问
+//! 持有锁期间关闭当前核心的中断, 避免中断处理程序再次获取同一个锁
+
+use super::intr::{pop_off, push_off};
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "lock-debug")]
+use crate::task::hart_id;
+#[cfg(feature = "lock-debug")]
+use core::panic::Location;
+#[cfg(feature = "lock-debug")]
+use core::sync::atomic::{AtomicPtr, AtomicUsize};
+
+/// 自旋超过该次数时认为发生了死锁
+#[cfg(feature = "lock-debug")]
+const DEADLOCK_SPIN_LIMIT: usize = 1 << 28;
+
+/// 自旋锁
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    /// 持有者的hartid + 1, 0表示没有持有者
+    #[cfg(feature = "lock-debug")]
+    owner: AtomicUsize,
+    /// 持有者获取锁的位置
+    #[cfg(feature = "lock-debug")]
+    location: AtomicPtr<Location<'static>>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    /// 创建一个未上锁的自旋锁
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            #[cfg(feature = "lock-debug")]
+            owner: AtomicUsize::new(0),
+            #[cfg(feature = "lock-debug")]
+            location: AtomicPtr::new(core::ptr::null_mut()),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// 获取锁, 锁被占用时自旋等待
+    /// 同一个核心重复获取会死锁
+    #[track_caller]
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        push_off();
+        #[cfg(feature = "lock-debug")]
+        self.check_recursive();
+
+        #[cfg(feature = "lock-debug")]
+        let mut spins = 0usize;
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // 只读等待, 减少总线上的写操作
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+                #[cfg(feature = "lock-debug")]
+                {
+                    spins += 1;
+                    if spins == DEADLOCK_SPIN_LIMIT {
+                        self.report_deadlock();
+                    }
+                }
+            }
+        }
+
+        self.acquired()
+    }
+
+    /// 尝试获取锁, 锁被占用时返回None
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        push_off();
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            Some(self.acquired())
+        } else {
+            pop_off();
+            None
+        }
+    }
+
+    #[track_caller]
+    fn acquired(&self) -> SpinLockGuard<'_, T> {
+        #[cfg(feature = "lock-debug")]
+        {
+            self.owner.store(hart_id() + 1, Ordering::Relaxed);
+            self.location
+                .store(Location::caller() as *const _ as *mut _, Ordering::Relaxed);
+        }
+        SpinLockGuard { lock: self }
+    }
+
+    /// 同一个核心重复获取同一个锁
+    #[cfg(feature = "lock-debug")]
+    #[track_caller]
+    fn check_recursive(&self) {
+        if self.owner.load(Ordering::Relaxed) == hart_id() + 1 {
+            panic!(
+                "SpinLock: hart {} locks again at {}, already held at {}",
+                hart_id(),
+                Location::caller(),
+                self.owner_location()
+            );
+        }
+    }
+
+    #[cfg(feature = "lock-debug")]
+    #[track_caller]
+    fn report_deadlock(&self) {
+        panic!(
+            "SpinLock: possible deadlock, hart {} waiting at {}, held by hart {} at {}",
+            hart_id(),
+            Location::caller(),
+            self.owner.load(Ordering::Relaxed).wrapping_sub(1) as isize,
+            self.owner_location()
+        );
+    }
+
+    #[cfg(feature = "lock-debug")]
+    fn owner_location(&self) -> &'static Location<'static> {
+        let location = self.location.load(Ordering::Relaxed);
+        if location.is_null() {
+            Location::caller()
+        } else {
+            unsafe { &*location }
+        }
+    }
+}
+
+/// 自旋锁的RAII guard, 离开作用域时释放锁并恢复中断
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "lock-debug")]
+        self.lock.owner.store(0, Ordering::Relaxed);
+        self.lock.locked.store(false, Ordering::Release);
+        pop_off();
+    }
+}
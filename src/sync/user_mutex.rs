@@ -0,0 +1,72 @@
+//! User mutex: 通过系统调用提供给用户程序的互斥锁
+//! 与Mutex不同, 加锁和解锁发生在两次系统调用中, 因此没有guard
+
+use super::{SpinLock, WaitQueue};
+use crate::task::{current_interrupted, suspend_current_and_run_next};
+
+/// 用户程序使用的互斥锁
+pub struct UserMutex {
+    /// 锁被占用时阻塞, 否则让出CPU以后重试
+    blocking: bool,
+    locked: SpinLock<bool>,
+    /// 等待该锁的线程
+    wait_queue: WaitQueue,
+}
+
+impl UserMutex {
+    /// 创建一个未上锁的互斥锁
+    pub fn new(blocking: bool) -> Self {
+        Self {
+            blocking,
+            locked: SpinLock::new(false),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    /// 尝试获取锁
+    fn try_lock(&self) -> bool {
+        let mut locked = self.locked.lock();
+        if *locked {
+            return false;
+        }
+        *locked = true;
+        true
+    }
+
+    /// 获取锁, 锁被占用时阻塞或者让出CPU
+    /// interrupted返回true或者被信号打断时放弃等待, 此时返回false
+    pub fn lock<F: Fn() -> bool>(&self, interrupted: F) -> bool {
+        if self.blocking {
+            let mut acquired = false;
+            self.wait_queue.wait_until_interruptible(|| {
+                acquired = self.try_lock();
+                acquired || interrupted()
+            });
+            acquired
+        } else {
+            loop {
+                if self.try_lock() {
+                    return true;
+                }
+                if interrupted() || current_interrupted() {
+                    return false;
+                }
+                suspend_current_and_run_next();
+            }
+        }
+    }
+
+    /// 释放锁并唤醒一个等待的线程, 没有上锁时返回false
+    pub fn unlock(&self) -> bool {
+        let mut locked = self.locked.lock();
+        if !*locked {
+            return false;
+        }
+        *locked = false;
+        drop(locked);
+        if self.blocking {
+            self.wait_queue.wake_one();
+        }
+        true
+    }
+}
@@ -0,0 +1,99 @@
+//! Wait queue: 任务在这里阻塞等待某个事件
+
+use super::SpinLock;
+use crate::task::{
+    block_current_and_run_next, current_task, wakeup_task, TaskControlBlock, TaskStatus,
+};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::hint::spin_loop;
+
+/// 等待队列
+pub struct WaitQueue {
+    queue: SpinLock<VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl WaitQueue {
+    /// 创建一个空的等待队列
+    pub const fn new() -> Self {
+        Self {
+            queue: SpinLock::new(VecDeque::new()),
+        }
+    }
+
+    /// 阻塞当前任务, 直到condition返回true
+    /// condition在持有队列锁时检查, 因此唤醒者只要在条件成立以后调用wake_*, 唤醒就不会丢失
+    /// 唤醒者调用wake_*时不能持有condition中需要获取的锁
+    pub fn wait_until<F: FnMut() -> bool>(&self, condition: F) {
+        self.wait(condition, false);
+    }
+
+    /// 与wait_until相同, 但是当前任务被信号打断时放弃等待并返回false
+    /// condition已经成立时返回true, 即使同时被打断
+    pub fn wait_until_interruptible<F: FnMut() -> bool>(&self, condition: F) -> bool {
+        self.wait(condition, true)
+    }
+
+    fn wait<F: FnMut() -> bool>(&self, mut condition: F, interruptible: bool) -> bool {
+        loop {
+            let mut queue = self.queue.lock();
+            if condition() {
+                // 被信号唤醒的任务仍然在队列中
+                if interruptible {
+                    if let Some(task) = current_task() {
+                        queue.retain(|other| !Arc::ptr_eq(other, &task));
+                    }
+                }
+                return true;
+            }
+
+            match current_task() {
+                Some(task) => {
+                    // 在任务的锁中检查interrupted并标记为Blocked, send_signal的唤醒不会丢失
+                    let mut inner = task.inner_exclusive_access();
+                    if interruptible && inner.interrupted {
+                        drop(inner);
+                        queue.retain(|other| !Arc::ptr_eq(other, &task));
+                        return false;
+                    }
+                    inner.task_status = TaskStatus::Blocked;
+                    inner.interruptible = interruptible;
+                    drop(inner);
+                    queue.push_back(task);
+                    drop(queue);
+                    block_current_and_run_next();
+                }
+                None => {
+                    // 启动阶段和idle控制流没有可以阻塞的任务, 只能自旋等待
+                    drop(queue);
+                    spin_loop();
+                }
+            }
+        }
+    }
+
+    /// 唤醒最早等待的一个任务, 队列为空时返回false
+    pub fn wake_one(&self) -> bool {
+        let task = self.queue.lock().pop_front();
+        match task {
+            Some(task) => {
+                wakeup_task(task);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 唤醒所有等待的任务
+    pub fn wake_all(&self) {
+        let tasks = core::mem::take(&mut *self.queue.lock());
+        for task in tasks {
+            wakeup_task(task);
+        }
+    }
+
+    /// 是否有任务在等待
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().is_empty()
+    }
+}
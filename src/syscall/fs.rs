@@ -1,80 +1,198 @@
 //! Syscall: File and filesystem-related syscalls
 
-use crate::fs::{open_file, OpenFlags, Stat, ROOT_INODE};
-use crate::mm::{translated_and_write_bytes, translated_byte_buffer, translated_str, UserBuffer};
-use crate::task::{current_task, current_user_token};
+use super::uaccess::{
+    check_user_buffer, copy_str_from_user, copy_to_user, read_to_user, write_from_user, UserPtr,
+};
+use crate::config::MAX_FD_NUM;
+use crate::errno::Errno;
+use crate::fs::{
+    absolute_path, is_dir_path, link, make_dir, make_pipe, mount, open, stat, umount, unlink, File,
+    FileDescriptor, OpenFlags, SeekFrom, Stat, StatMode,
+};
+use crate::task::current_process;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// 相对于当前工作目录解析路径
+pub const AT_FDCWD: isize = -100;
+/// unlinkat删除目录
+const AT_REMOVEDIR: usize = 0x200;
+/// 没有符号链接, 总是可以接受
+const AT_SYMLINK_NOFOLLOW: usize = 0x100;
+/// 没有自动挂载, 总是可以接受
+const AT_NO_AUTOMOUNT: usize = 0x800;
+/// path为空时获取dirfd本身
+const AT_EMPTY_PATH: usize = 0x1000;
+/// statx的同步方式, 所有数据都在本地, 总是可以接受
+const AT_STATX_SYNC_TYPE: usize = 0x6000;
+
+/// statx返回的字段: 除了创建时间和挂载id以外的基本字段
+const STATX_BASIC_STATS: u32 = 0x7ff;
+
+/// linux_dirent64中的文件类型
+const DT_FIFO: u8 = 1;
+const DT_CHR: u8 = 2;
+const DT_DIR: u8 = 4;
+const DT_BLK: u8 = 6;
+const DT_REG: u8 = 8;
+
+/// lseek的whence
+const SEEK_SET: usize = 0;
+const SEEK_CUR: usize = 1;
+const SEEK_END: usize = 2;
 
 /// sys_write handler
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    trace!("[Kernel] pid[{}] sys_write", current_task().unwrap().pid.0);
-
-    let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
-
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
-        drop(inner);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
-    } else {
-        -1
+    trace!("[Kernel] pid[{}] sys_write", current_process().pid.0);
+
+    let file = match current_file(fd) {
+        Ok(file) if file.writable() => file,
+        _ => return Errno::EBADF.into(),
+    };
+    match write_from_user(buf, len, |_, buf| file.write(buf)) {
+        Ok(written) => written as isize,
+        Err(errno) => errno.into(),
     }
 }
 
 /// sys_read
 pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
-    trace!("[Kernel] pid[{}] sys_read", current_task().unwrap().pid.0);
-
-    let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
+    trace!("[Kernel] pid[{}] sys_read", current_process().pid.0);
+
+    let file = match current_file(fd) {
+        Ok(file) if file.readable() => file,
+        _ => return Errno::EBADF.into(),
+    };
+    // 管道和控制台读到数据以后就返回, 不能继续读下一块
+    let more = file.inode().is_some();
+    match read_to_user(buf, len, more, |_, buf| file.read(buf)) {
+        Ok(read) => read as isize,
+        Err(errno) => errno.into(),
+    }
+}
 
-    if fd >= inner.fd_table.len() {
-        return -1;
+/// 移动fd的读写位置, 返回新的位置
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_lseek", current_process().pid.0);
+
+    let pos = match whence {
+        SEEK_SET if offset >= 0 => SeekFrom::Start(offset as usize),
+        SEEK_CUR => SeekFrom::Current(offset),
+        SEEK_END => SeekFrom::End(offset),
+        _ => return Errno::EINVAL.into(),
+    };
+    let file = match current_file(fd) {
+        Ok(file) => file,
+        Err(errno) => return errno.into(),
+    };
+    match file.seek(pos) {
+        Some(offset) if offset <= isize::MAX as usize => offset as isize,
+        // 管道和控制台不能定位, 其他文件是位置越界
+        Some(_) => Errno::EINVAL.into(),
+        None if file.inode().is_none() => Errno::ESPIPE.into(),
+        None => Errno::EINVAL.into(),
     }
+}
 
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
-        drop(inner);
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
-    } else {
-        -1
+/// 从offset处读取, 不改变fd的读写位置
+pub fn sys_pread64(fd: usize, buf: *const u8, len: usize, offset: isize) -> isize {
+    trace!("[Kernel] pid[{}] sys_pread64", current_process().pid.0);
+
+    let file = match current_file(fd) {
+        Ok(file) if file.readable() => file,
+        _ => return Errno::EBADF.into(),
+    };
+    if offset < 0 {
+        return Errno::EINVAL.into();
+    }
+    let more = file.inode().is_some();
+    let result = read_to_user(buf, len, more, |done, buf| {
+        file.read_at(offset as usize + done, buf)
+            .ok_or(Errno::ESPIPE)
+    });
+    match result {
+        Ok(read) => read as isize,
+        Err(errno) => errno.into(),
     }
 }
 
-pub fn sys_open(path: *const u8, flags: u32) -> isize {
-    trace!("[Kernel] pid[{}] sys_open", current_task().unwrap().pid.0);
+/// 写入offset处, 不改变fd的读写位置
+pub fn sys_pwrite64(fd: usize, buf: *const u8, len: usize, offset: isize) -> isize {
+    trace!("[Kernel] pid[{}] sys_pwrite64", current_process().pid.0);
 
-    let task = current_task().unwrap();
-    let token = current_user_token();
-    let path = translated_str(token, path);
+    let file = match current_file(fd) {
+        Ok(file) if file.writable() => file,
+        _ => return Errno::EBADF.into(),
+    };
+    if offset < 0 {
+        return Errno::EINVAL.into();
+    }
+    let result = write_from_user(buf, len, |done, buf| {
+        file.write_at(offset as usize + done, buf)
+            .ok_or(Errno::ESPIPE)
+    });
+    match result {
+        Ok(written) => written as isize,
+        Err(errno) => errno.into(),
+    }
+}
 
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
-        let mut inner = task.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
-    } else {
-        -1
+/// 当前任务fd对应的文件
+fn current_file(fd: usize) -> Result<Arc<dyn File + Send + Sync>, Errno> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    inner
+        .fd_table
+        .get(fd)
+        .and_then(|fd| fd.as_ref())
+        .map(|fd| fd.file.clone())
+        .ok_or(Errno::EBADF)
+}
+
+pub fn sys_open(dirfd: isize, path: *const u8, flags: u32) -> isize {
+    trace!("[Kernel] pid[{}] sys_open", current_process().pid.0);
+
+    let process = current_process();
+    let path = match user_path(dirfd, path) {
+        Ok(path) => path,
+        Err(errno) => return errno.into(),
+    };
+
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return Errno::EINVAL.into(),
+    };
+    // CLOEXEC属于文件描述符而不是打开的文件
+    match open(path.as_str(), flags - OpenFlags::CLOEXEC) {
+        Ok(file) => {
+            let mut inner = process.inner_exclusive_access();
+            let fd = match inner.alloc_fd() {
+                Ok(fd) => fd,
+                Err(errno) => return errno.into(),
+            };
+            inner.fd_table[fd] = Some(FileDescriptor::new(
+                file,
+                flags.contains(OpenFlags::CLOEXEC),
+            ));
+            fd as isize
+        }
+        Err(errno) => errno.into(),
     }
 }
 
 pub fn sys_close(fd: usize) -> isize {
-    trace!("[Kernel] pid[{}] sys_close", current_task().unwrap().pid.0);
+    trace!("[Kernel] pid[{}] sys_close", current_process().pid.0);
 
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
 
     if fd >= inner.fd_table.len() {
-        return -1;
+        return Errno::EBADF.into();
     }
 
     if inner.fd_table[fd].is_none() {
-        return -1;
+        return Errno::EBADF.into();
     }
 
     inner.fd_table[fd].take();
@@ -82,56 +200,408 @@ pub fn sys_close(fd: usize) -> isize {
     0
 }
 
+/// 复制old_fd到编号最小的空闲描述符, 新描述符没有close-on-exec标志
+pub fn sys_dup(old_fd: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_dup", current_process().pid.0);
+
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(old_fd) {
+        Some(Some(fd)) => fd.file.clone(),
+        _ => return Errno::EBADF.into(),
+    };
+
+    let new_fd = match inner.alloc_fd() {
+        Ok(fd) => fd,
+        Err(errno) => return errno.into(),
+    };
+    inner.fd_table[new_fd] = Some(FileDescriptor::new(file, false));
+    new_fd as isize
+}
+
+/// 复制old_fd到new_fd, new_fd已经打开时先关闭
+/// flags只能包含O_CLOEXEC
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    trace!("[Kernel] pid[{}] sys_dup3", current_process().pid.0);
+
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) if (flags - OpenFlags::CLOEXEC).is_empty() => flags,
+        _ => return Errno::EINVAL.into(),
+    };
+    if new_fd >= MAX_FD_NUM {
+        return Errno::EBADF.into();
+    }
+    if old_fd == new_fd {
+        return Errno::EINVAL.into();
+    }
+
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(old_fd) {
+        Some(Some(fd)) => fd.file.clone(),
+        _ => return Errno::EBADF.into(),
+    };
+
+    if new_fd >= inner.fd_table.len() {
+        inner.fd_table.resize(new_fd + 1, None);
+    }
+    let closed = inner.fd_table[new_fd].replace(FileDescriptor::new(
+        file,
+        flags.contains(OpenFlags::CLOEXEC),
+    ));
+    // 在释放锁以后关闭原来的文件
+    drop(inner);
+    drop(closed);
+    new_fd as isize
+}
+
+/// 创建管道, 将读端和写端的fd依次写入pipe[0]和pipe[1]
+pub fn sys_pipe(pipe: *mut usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_pipe", current_process().pid.0);
+
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let (pipe_read, pipe_write) = make_pipe();
+    let read_fd = match inner.alloc_fd() {
+        Ok(fd) => fd,
+        Err(errno) => return errno.into(),
+    };
+    inner.fd_table[read_fd] = Some(FileDescriptor::new(pipe_read, false));
+    let write_fd = match inner.alloc_fd() {
+        Ok(fd) => fd,
+        Err(errno) => {
+            let read_end = inner.fd_table[read_fd].take();
+            drop(inner);
+            drop(read_end);
+            return errno.into();
+        }
+    };
+    inner.fd_table[write_fd] = Some(FileDescriptor::new(pipe_write, false));
+    drop(inner);
+
+    if let Err(errno) = UserPtr::new(pipe as *const [usize; 2]).write(&[read_fd, write_fd]) {
+        // 地址无效时关闭刚刚创建的管道
+        let mut inner = process.inner_exclusive_access();
+        let read_end = inner.fd_table[read_fd].take();
+        let write_end = inner.fd_table[write_fd].take();
+        drop(inner);
+        drop(read_end);
+        drop(write_end);
+        return errno.into();
+    }
+    0
+}
+
 pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
-    trace!("[Kernel] pid[{}] sys_fstat", current_task().unwrap().pid.0);
-
-    let task = current_task().unwrap();
-    let os_inode = {
-        // 获取 TCB 的可变引用，仅在此作用域内有效
-        let inner = task.inner_exclusive_access();
-        // 检查 fd 有效性
-        if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
-            return -1;
+    trace!("[Kernel] pid[{}] sys_fstat", current_process().pid.0);
+
+    current_file(fd)
+        .and_then(|file| UserPtr::new(st).write(&file.get_stat()))
+        .map_or_else(|errno| errno.into(), |_| 0)
+}
+
+/// 获取dirfd和path指定的文件的Stat
+pub fn sys_fstatat(dirfd: isize, path: *const u8, st: *mut Stat, flags: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_fstatat", current_process().pid.0);
+
+    stat_at(dirfd, path, flags)
+        .and_then(|stat| UserPtr::new(st).write(&stat))
+        .map_or_else(|errno| errno.into(), |_| 0)
+}
+
+/// 与Linux的struct statx布局一致
+#[repr(C)]
+pub struct Statx {
+    mask: u32,
+    blksize: u32,
+    attributes: u64,
+    nlink: u32,
+    uid: u32,
+    gid: u32,
+    mode: u16,
+    __spare0: u16,
+    ino: u64,
+    size: u64,
+    blocks: u64,
+    attributes_mask: u64,
+    atime: StatxTimestamp,
+    btime: StatxTimestamp,
+    ctime: StatxTimestamp,
+    mtime: StatxTimestamp,
+    rdev_major: u32,
+    rdev_minor: u32,
+    dev_major: u32,
+    dev_minor: u32,
+    /// stx_mnt_id, stx_dio_*_align和保留字段
+    __spare: [u64; 14],
+}
+
+#[repr(C)]
+struct StatxTimestamp {
+    sec: i64,
+    nsec: u32,
+    __reserved: i32,
+}
+
+impl StatxTimestamp {
+    fn new(sec: i64, nsec: i64) -> Self {
+        Self {
+            sec,
+            nsec: nsec as u32,
+            __reserved: 0,
+        }
+    }
+}
+
+/// Linux设备号中的主设备号和次设备号
+fn split_dev(dev: u64) -> (u32, u32) {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    (major as u32, minor as u32)
+}
+
+impl From<Stat> for Statx {
+    fn from(stat: Stat) -> Self {
+        let (rdev_major, rdev_minor) = split_dev(stat.rdev);
+        let (dev_major, dev_minor) = split_dev(stat.dev);
+        Self {
+            mask: STATX_BASIC_STATS,
+            blksize: stat.blksize as u32,
+            attributes: 0,
+            nlink: stat.nlink,
+            uid: stat.uid,
+            gid: stat.gid,
+            mode: stat.mode as u16,
+            __spare0: 0,
+            ino: stat.ino,
+            size: stat.size as u64,
+            blocks: stat.blocks as u64,
+            attributes_mask: 0,
+            atime: StatxTimestamp::new(stat.atime_sec, stat.atime_nsec),
+            btime: StatxTimestamp::new(0, 0),
+            ctime: StatxTimestamp::new(stat.ctime_sec, stat.ctime_nsec),
+            mtime: StatxTimestamp::new(stat.mtime_sec, stat.mtime_nsec),
+            rdev_major,
+            rdev_minor,
+            dev_major,
+            dev_minor,
+            __spare: [0; 14],
+        }
+    }
+}
+
+/// 获取dirfd和path指定的文件的扩展Stat, 总是返回所有基本字段, 忽略mask
+pub fn sys_statx(
+    dirfd: isize,
+    path: *const u8,
+    flags: usize,
+    _mask: u32,
+    statx: *mut Statx,
+) -> isize {
+    trace!("[Kernel] pid[{}] sys_statx", current_process().pid.0);
+
+    stat_at(dirfd, path, flags)
+        .and_then(|stat| UserPtr::new(statx).write(&Statx::from(stat)))
+        .map_or_else(|errno| errno.into(), |_| 0)
+}
+
+/// fstatat和statx共用的查找
+/// 设置AT_EMPTY_PATH并且path为空时获取dirfd本身, 可以是管道等没有路径的文件
+fn stat_at(dirfd: isize, path: *const u8, flags: usize) -> Result<Stat, Errno> {
+    if flags & !(AT_SYMLINK_NOFOLLOW | AT_NO_AUTOMOUNT | AT_EMPTY_PATH | AT_STATX_SYNC_TYPE) != 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    let path = copy_str_from_user(path)?;
+    if path.is_empty() {
+        if flags & AT_EMPTY_PATH == 0 {
+            return Err(Errno::ENOENT);
         }
-        // 克隆 OSInode 的 Arc，确保所有权转移到外部
-        inner.fd_table[fd].clone()
-    }; // 此处 inner 的作用域结束，自动调用 drop（显式或隐式）
+        if dirfd != AT_FDCWD {
+            return current_file(dirfd as usize).map(|file| file.get_stat());
+        }
+    }
+    stat(&resolve_path(dirfd, &path)?).ok_or(Errno::ENOENT)
+}
+
+/// 为old_path创建硬链接new_path
+pub fn sys_linkat(
+    old_dirfd: isize,
+    old_path: *const u8,
+    new_dirfd: isize,
+    new_path: *const u8,
+    _flags: usize,
+) -> isize {
+    trace!("[Kernel] pid[{}] sys_linkat", current_process().pid.0);
+
+    let result = user_path(old_dirfd, old_path).and_then(|old_path| {
+        let new_path = user_path(new_dirfd, new_path)?;
+        link(&old_path, &new_path)
+    });
+    result.map_or_else(|errno| errno.into(), |_| 0)
+}
 
-    let token = current_user_token();
+/// 删除目录项, flags包含AT_REMOVEDIR时删除空目录
+pub fn sys_unlinkat(dirfd: isize, path: *const u8, flags: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_unlinkat", current_process().pid.0);
 
-    let stat = os_inode.unwrap().get_stat();
+    user_path(dirfd, path)
+        .and_then(|path| unlink(&path, flags & AT_REMOVEDIR != 0))
+        .map_or_else(|errno| errno.into(), |_| 0)
+}
 
-    let st_ptr = &stat as *const Stat as *const u8;
-    let st_len = core::mem::size_of::<Stat>();
+/// 创建目录, 父目录所在的文件系统不支持目录时返回EPERM
+/// easy-fs只有根目录, 因此目前只能在tmpfs等支持目录的文件系统中创建目录
+pub fn sys_mkdirat(dirfd: isize, path: *const u8, _mode: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_mkdirat", current_process().pid.0);
 
-    translated_and_write_bytes(token, st as usize as *const u8, st_ptr, st_len);
+    user_path(dirfd, path)
+        .and_then(|path| make_dir(&path))
+        .map_or_else(|errno| errno.into(), |_| 0)
+}
 
+/// 修改当前工作目录
+pub fn sys_chdir(path: *const u8) -> isize {
+    trace!("[Kernel] pid[{}] sys_chdir", current_process().pid.0);
+
+    let path = match user_path(AT_FDCWD, path) {
+        Ok(path) => path,
+        Err(errno) => return errno.into(),
+    };
+    if !is_dir_path(&path) {
+        let errno = if stat(&path).is_some() {
+            Errno::ENOTDIR
+        } else {
+            Errno::ENOENT
+        };
+        return errno.into();
+    }
+    current_process().inner_exclusive_access().cwd = path;
     0
 }
 
-pub fn sys_linkat(old_path: *const u8, new_path: *const u8) -> isize {
-    trace!("[Kernel] pid[{}] sys_linkat", current_task().unwrap().pid.0);
+/// 将名为fstype的文件系统挂载到target, source的含义由文件系统决定
+/// data是以逗号分隔的挂载选项字符串, 不支持flags
+pub fn sys_mount(
+    source: *const u8,
+    target: *const u8,
+    fstype: *const u8,
+    _flags: usize,
+    data: *const u8,
+) -> isize {
+    trace!("[Kernel] pid[{}] sys_mount", current_process().pid.0);
+
+    let optional_str = |ptr: *const u8| {
+        if ptr.is_null() {
+            Ok(String::new())
+        } else {
+            copy_str_from_user(ptr)
+        }
+    };
+    let result = optional_str(source).and_then(|source| {
+        let data = optional_str(data)?;
+        let fstype = copy_str_from_user(fstype)?;
+        let target = user_path(AT_FDCWD, target)?;
+        mount(&source, &target, &fstype, &data)
+    });
+    result.map_or_else(|errno| errno.into(), |_| 0)
+}
+
+/// 卸载target上的文件系统, 不支持任何flags
+pub fn sys_umount2(target: *const u8, flags: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_umount2", current_process().pid.0);
+
+    if flags != 0 {
+        return Errno::EINVAL.into();
+    }
+    user_path(AT_FDCWD, target)
+        .and_then(|target| umount(&target))
+        .map_or_else(|errno| errno.into(), |_| 0)
+}
+
+/// 将当前工作目录写入buf, 返回包括结尾0的长度
+pub fn sys_getcwd(buf: *mut u8, size: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_getcwd", current_process().pid.0);
+
+    let mut cwd = current_process().inner_exclusive_access().cwd.clone();
+    cwd.push('\0');
+    if cwd.len() > size {
+        return Errno::ERANGE.into();
+    }
+    match copy_to_user(buf as usize, cwd.as_bytes()) {
+        Ok(()) => cwd.len() as isize,
+        Err(errno) => errno.into(),
+    }
+}
 
-    let token = current_user_token();
+/// 从目录的当前位置读取linux_dirent64, 返回写入的字节数, 读完时返回0
+pub fn sys_getdents64(fd: usize, buf: *mut u8, len: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_getdents64", current_process().pid.0);
+
+    let file = match current_file(fd) {
+        Ok(file) => file,
+        Err(errno) => return errno.into(),
+    };
+    // 读取目录项会移动读写位置, 因此先检查缓冲区
+    if let Err(errno) = check_user_buffer(buf, len, true) {
+        return errno.into();
+    }
+    let entries = match file.read_dir(len) {
+        Ok(entries) => entries,
+        Err(errno) => return errno.into(),
+    };
+
+    let mut dirents: Vec<u8> = Vec::new();
+    for entry in entries {
+        let reclen = entry.dirent64_len();
+        let start = dirents.len();
+        dirents.extend_from_slice(&entry.ino.to_ne_bytes());
+        dirents.extend_from_slice(&(entry.off as i64).to_ne_bytes());
+        dirents.extend_from_slice(&(reclen as u16).to_ne_bytes());
+        dirents.push(dirent_type(entry.mode));
+        dirents.extend_from_slice(entry.name.as_bytes());
+        dirents.resize(start + reclen, 0);
+    }
 
-    let old_path_str = translated_str(token, old_path);
-    let new_path_str = translated_str(token, new_path);
-    if old_path_str == new_path_str {
-        // 不允许同名链接
-        return -1;
+    match copy_to_user(buf as usize, &dirents) {
+        Ok(()) => dirents.len() as isize,
+        Err(errno) => errno.into(),
     }
+}
 
-    ROOT_INODE.linkat(old_path_str.as_str(), new_path_str.as_str())
+/// linux_dirent64中的d_type
+fn dirent_type(mode: StatMode) -> u8 {
+    // BLK包含了DIR和CHR的位, 需要比较整个类型
+    if mode == StatMode::DIR {
+        DT_DIR
+    } else if mode == StatMode::FIFO {
+        DT_FIFO
+    } else if mode == StatMode::CHR {
+        DT_CHR
+    } else if mode == StatMode::BLK {
+        DT_BLK
+    } else {
+        DT_REG
+    }
 }
 
-pub fn sys_unlinkat(path: *const u8) -> isize {
-    trace!(
-        "[Kernel] pid[{}] sys_unlinkat",
-        current_task().unwrap().pid.0
-    );
+/// 从用户内存读取路径, 然后相对于dirfd解析
+pub fn user_path(dirfd: isize, path: *const u8) -> Result<String, Errno> {
+    resolve_path(dirfd, &copy_str_from_user(path)?)
+}
 
-    let token = current_user_token();
-    let path_str = translated_str(token, path);
+/// 将dirfd和path转换为规范的绝对路径
+/// dirfd为AT_FDCWD时相对于当前工作目录, 否则相对于dirfd打开的目录
+/// dirfd不是打开的目录时返回ENOTDIR
+pub fn resolve_path(dirfd: isize, path: &str) -> Result<String, Errno> {
+    if path.starts_with('/') {
+        return Ok(absolute_path("/", path));
+    }
 
-    ROOT_INODE.unlinkat(path_str.as_str())
+    let base = if dirfd == AT_FDCWD {
+        current_process().inner_exclusive_access().cwd.clone()
+    } else {
+        current_file(dirfd as usize)?.path().ok_or(Errno::ENOTDIR)?
+    };
+    Ok(absolute_path(&base, path))
 }
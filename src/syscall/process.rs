@@ -1,18 +1,21 @@
 //! Syscall: Process management syscalls
-use crate::config::{MAX_SYSCALL_NUM, PAGE_SIZE};
-use crate::fs::{open_file, OpenFlags};
-use crate::mm::{
-    check_map_area_mapping, check_map_area_unmapping, translated_and_write_bytes,
-    translated_refmut, translated_str, MapArea, MapPermission, MapType, VirtAddr,
-};
+use super::fs::{user_path, AT_FDCWD};
+use super::uaccess::UserPtr;
+use crate::config::{CLOCK_FREQ, MAX_SYSCALL_NUM, PAGE_SIZE, USER_SPACE_END};
+use crate::errno::Errno;
+use crate::fs::{is_dir_path, open_file, OSInode, OpenFlags};
+use crate::mm::{MapPermission, MmapFile, VirtAddr};
 use crate::task::{
-    add_task, current_task, current_task_info_inner, current_user_token, exit_current_and_run_next,
-    mapping_address_space_for_current_task, suspend_current_and_run_next,
-    unmapping_address_space_for_current_task, TaskControlBlock, TaskStatus,
+    add_task, current_process, current_task, current_task_info_inner, current_trap_cx,
+    exit_current_and_run_next, pid2process, set_sched_policy, suspend_current_and_run_next,
+    unmapping_address_space_for_current_task, ProcessControlBlock, SchedPolicy, SignalAction,
+    SignalFlags, SignalFrame, TaskStatus, MAX_PRIO,
 };
-use crate::timer::{get_time_ms, get_time_us};
+use crate::timer::{get_time, get_time_ms, get_time_us, sleep_ms, sleep_until};
 use alloc::sync::Arc;
 
+const NSEC_PER_SEC: usize = 1_000_000_000;
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct TimeVal {
@@ -20,6 +23,35 @@ pub struct TimeVal {
     pub usec: usize,
 }
 
+/// sys_nanosleep和sys_futex使用的时间
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+impl TimeSpec {
+    /// 时钟计数对应的时间
+    pub fn from_ticks(ticks: usize) -> Self {
+        Self {
+            sec: ticks / CLOCK_FREQ,
+            nsec: ticks % CLOCK_FREQ * NSEC_PER_SEC / CLOCK_FREQ,
+        }
+    }
+
+    /// 转换为时钟计数, nsec超出范围时返回EINVAL
+    pub fn to_ticks(&self) -> Result<usize, Errno> {
+        if self.nsec >= NSEC_PER_SEC {
+            return Err(Errno::EINVAL);
+        }
+        Ok(self
+            .sec
+            .saturating_mul(CLOCK_FREQ)
+            .saturating_add(self.nsec * CLOCK_FREQ / NSEC_PER_SEC))
+    }
+}
+
 /// Task Info
 #[allow(dead_code)]
 pub struct TaskInfo {
@@ -35,7 +67,7 @@ pub struct TaskInfo {
 pub fn sys_exit(exit_code: i32) -> ! {
     trace!(
         "[Kernel] pid[{}] exited with code {}",
-        current_task().unwrap().pid.0,
+        current_process().pid.0,
         exit_code
     );
     exit_current_and_run_next(exit_code);
@@ -44,17 +76,47 @@ pub fn sys_exit(exit_code: i32) -> ! {
 
 /// current task gives up resources for other task
 pub fn sys_yield() -> isize {
-    trace!("[Kernel] pid[{}] sys_yield", current_task().unwrap().pid.0);
+    trace!("[Kernel] pid[{}] sys_yield", current_process().pid.0);
     suspend_current_and_run_next();
     0
 }
 
+/// 睡眠req指定的时间
+/// 被信号打断时返回EINTR, rem不为空时写入剩余的时间
+pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> isize {
+    trace!("[Kernel] pid[{}] sys_nanosleep", current_process().pid.0);
+
+    let ticks = match UserPtr::new(req).read().and_then(|req| req.to_ticks()) {
+        Ok(ticks) => ticks,
+        Err(errno) => return errno.into(),
+    };
+    let expire = get_time().saturating_add(ticks);
+    if sleep_until(expire) {
+        return 0;
+    }
+    let rem = UserPtr::new(rem);
+    if !rem.is_null() {
+        let remain = TimeSpec::from_ticks(expire.saturating_sub(get_time()));
+        if let Err(errno) = rem.write(&remain) {
+            return errno.into();
+        }
+    }
+    Errno::EINTR.into()
+}
+
+/// 睡眠ms毫秒, 被信号打断时返回EINTR
+pub fn sys_sleep(ms: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_sleep", current_process().pid.0);
+    if sleep_ms(ms) {
+        0
+    } else {
+        Errno::EINTR.into()
+    }
+}
+
 /// get time
 pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
-    trace!(
-        "[Kernel] pid[{}] sys_get_time",
-        current_task().unwrap().pid.0
-    );
+    trace!("[Kernel] pid[{}] sys_get_time", current_process().pid.0);
 
     let us = get_time_us();
     let tv_inner = TimeVal {
@@ -62,25 +124,15 @@ pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
         usec: us % 1_000_000,
     };
 
-    let tv_inner_ptr = &tv_inner as *const TimeVal as *const u8;
-    let tv_inner_len = core::mem::size_of::<TimeVal>();
-
-    translated_and_write_bytes(
-        current_user_token(),
-        ts as usize as *const u8,
-        tv_inner_ptr,
-        tv_inner_len,
-    );
-
-    0
+    match UserPtr::new(ts).write(&tv_inner) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
 }
 
 /// get task info
 pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
-    trace!(
-        "[Kernel] pid[{}] sys_task_info",
-        current_task().unwrap().pid.0
-    );
+    trace!("[Kernel] pid[{}] sys_task_info", current_process().pid.0);
     let current_task_info = current_task_info_inner();
 
     let task_info = TaskInfo {
@@ -89,24 +141,60 @@ pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
         time: get_time_ms() - current_task_info.first_run_time,
     };
 
-    let ptr = &task_info as *const TaskInfo as *const u8;
-    let len = core::mem::size_of::<TaskInfo>();
-
-    translated_and_write_bytes(current_user_token(), ti as usize as *const u8, ptr, len);
-
-    0
+    match UserPtr::new(ti).write(&task_info) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
 }
 
-pub fn sys_mmap(start: usize, len: usize, port: usize) -> isize {
-    trace!("[Kernel] pid[{}] sys_mmap", current_task().unwrap().pid.0);
+bitflags! {
+    /// 用于sys_mmap系统调用
+    pub struct MmapFlags: u32 {
+        /// 修改对其他映射可见, 并写回文件
+        const SHARED = 1 << 0;
+        /// 写时复制的私有映射
+        const PRIVATE = 1 << 1;
+        /// 必须映射到给定的地址
+        const FIXED = 1 << 4;
+        /// 不映射文件, 页面初始化为0
+        const ANONYMOUS = 1 << 5;
+    }
+}
 
-    if port & 0x07 == 0 || port & !0x7 != 0 || start & (PAGE_SIZE - 1) != 0 {
-        return -1;
+/// 映射[start, start + len), 返回映射的起始地址
+/// flags为0时兼容原来的三参数调用: 在start处建立匿名映射, 成功返回0, port不能为0
+/// MAP_FIXED会先取消范围内已有的映射, port为0(PROT_NONE)时保留地址但是不能访问
+pub fn sys_mmap(
+    start: usize,
+    len: usize,
+    port: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    trace!("[Kernel] pid[{}] sys_mmap", current_process().pid.0);
+
+    let legacy = flags == 0;
+    if (legacy && port & 0x07 == 0)
+        || port & !0x7 != 0
+        || start & (PAGE_SIZE - 1) != 0
+        || len == 0
+        || len > USER_SPACE_END
+    {
+        return Errno::EINVAL.into();
     }
 
-    let end = start + len;
-    let start_va = VirtAddr::from(start);
-    let end_va = VirtAddr::from(end);
+    let flags = match MmapFlags::from_bits(flags as u32) {
+        Some(flags) => flags,
+        None => return Errno::EINVAL.into(),
+    };
+    // MAP_SHARED和MAP_PRIVATE必须指定其中一个
+    if !legacy && flags.contains(MmapFlags::SHARED) == flags.contains(MmapFlags::PRIVATE) {
+        return Errno::EINVAL.into();
+    }
+    if offset & (PAGE_SIZE - 1) != 0 {
+        return Errno::EINVAL.into();
+    }
 
     let mut map_perm = MapPermission::U;
 
@@ -122,47 +210,112 @@ pub fn sys_mmap(start: usize, len: usize, port: usize) -> isize {
         map_perm |= MapPermission::X;
     }
 
-    // 仅用于检查
-    let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+    let shared = flags.contains(MmapFlags::SHARED);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
 
-    if check_map_area_mapping(current_user_token(), map_area) {
-        return -1;
+    let file = if legacy || flags.contains(MmapFlags::ANONYMOUS) {
+        None
+    } else {
+        let file = match inner.fd_table.get(fd) {
+            Some(Some(fd)) => fd.file.clone(),
+            _ => return Errno::EBADF.into(),
+        };
+        let inode = match file.inode() {
+            Some(inode) if inode.mmapable() => inode,
+            _ => return Errno::ENODEV.into(),
+        };
+        // 共享的可写映射会写回文件
+        if !file.readable() || (shared && map_perm.contains(MapPermission::W) && !file.writable()) {
+            return Errno::EACCES.into();
+        }
+        Some(MmapFile::new(inode, offset))
+    };
+
+    // 没有MAP_FIXED时start只是一个提示, 不可用时另外查找空闲地址
+    let mut start_va = VirtAddr::from(start);
+    let end = start.checked_add(len).filter(|&end| end <= USER_SPACE_END);
+    let fixed = legacy || flags.contains(MmapFlags::FIXED);
+    if !fixed
+        && (start == 0
+            || end.map_or(true, |end| {
+                inner.memory_set.is_overlapping(start_va, end.into())
+            }))
+    {
+        start_va = inner.memory_set.find_free_area(len);
     }
+    let end_va = match start_va.0.checked_add(len) {
+        Some(end) if end <= USER_SPACE_END => VirtAddr::from(end),
+        _ => return Errno::ENOMEM.into(),
+    };
 
-    mapping_address_space_for_current_task(start_va, end_va, map_perm);
+    if flags.contains(MmapFlags::FIXED) && !inner.memory_set.unmap_range(start_va, end_va) {
+        return Errno::ENOMEM.into();
+    }
 
-    0
+    // 物理页面在第一次访问时才分配
+    if !inner.mapping_address_space(start_va, end_va, map_perm, shared, file) {
+        return Errno::ENOMEM.into();
+    }
+
+    if legacy {
+        0
+    } else {
+        start_va.0 as isize
+    }
 }
 
+/// 取消[start, start + len)中的映射, 范围中没有映射时返回EINVAL
 pub fn sys_munmap(start: usize, len: usize) -> isize {
-    trace!("[Kernel] pid[{}] sys_munmap", current_task().unwrap().pid.0);
+    trace!("[Kernel] pid[{}] sys_munmap", current_process().pid.0);
 
-    if start % PAGE_SIZE != 0 {
-        return -1;
+    let end = match start.checked_add(len) {
+        Some(end) if end <= USER_SPACE_END => end,
+        _ => return Errno::EINVAL.into(),
+    };
+    if start % PAGE_SIZE != 0 || len == 0 {
+        return Errno::EINVAL.into();
     }
 
     let start_va = VirtAddr::from(start);
-    let end_va = VirtAddr::from(start + len);
+    let end_va = VirtAddr::from(end);
+
+    if !unmapping_address_space_for_current_task(start_va, end_va) {
+        return Errno::EINVAL.into();
+    }
 
-    let map_area = MapArea::new(start_va, end_va, MapType::Framed, MapPermission::U);
+    0
+}
 
-    if check_map_area_unmapping(current_user_token(), map_area) {
-        return -1;
+/// 将[start, start + len)中的MAP_SHARED文件映射写回文件
+pub fn sys_msync(start: usize, len: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_msync", current_process().pid.0);
+
+    if start % PAGE_SIZE != 0 {
+        return Errno::EINVAL.into();
     }
 
-    unmapping_address_space_for_current_task(start_va, end_va);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if !inner
+        .memory_set
+        .msync(VirtAddr::from(start), VirtAddr::from(start + len))
+    {
+        return Errno::ENOMEM.into();
+    }
 
     0
 }
 
 pub fn sys_fork() -> isize {
-    trace!("[Kernel] pid[{}] sys_fork", current_task().unwrap().pid.0);
+    trace!("[Kernel] pid[{}] sys_fork", current_process().pid.0);
 
-    let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
-    let new_pid = new_task.pid.0;
+    let current_process = current_process();
+    let new_process = current_process.fork();
+    let new_pid = new_process.pid.0;
+    let new_task = new_process.inner_exclusive_access().get_task(0).unwrap();
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
-    // 修改当前任务的返回值为0
+    // 修改子进程的返回值为0
     trap_cx.x[10] = 0;
 
     add_task(new_task);
@@ -171,158 +324,338 @@ pub fn sys_fork() -> isize {
 }
 
 pub fn sys_exec(path: *const u8) -> isize {
-    trace!("[Kernel] pid[{}] sys_exec", current_task().unwrap().pid.0);
+    trace!("[Kernel] pid[{}] sys_exec", current_process().pid.0);
 
     // 在用户地址空间中找到要执行的elf名字
-    let token = current_user_token();
-    let path_name = translated_str(token, path);
-
-    if let Some(app_inode) = open_file(path_name.as_str(), OpenFlags::RDONLY) {
-        let task = current_task().unwrap();
-        let all_data = app_inode.read_all();
-        task.exec(all_data.as_slice());
-        0
-    } else {
-        -1
+    let app_inode = match open_executable(path) {
+        Ok(app_inode) => app_inode,
+        Err(errno) => return errno.into(),
+    };
+    let process = current_process();
+    let all_data = app_inode.read_all();
+    match process.exec(all_data.as_slice()) {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
     }
 }
 
-/// sys_waitpid
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
-    trace!(
-        "[Kernel] pid[{}] sys_waitpid",
-        current_task().unwrap().pid.0
-    );
+/// 打开要执行的文件, 目录不能执行
+fn open_executable(path: *const u8) -> Result<Arc<OSInode>, Errno> {
+    let path = user_path(AT_FDCWD, path)?;
+    if is_dir_path(&path) {
+        return Err(Errno::EACCES);
+    }
+    open_file(&path, OpenFlags::RDONLY)
+}
 
-    // 获取当前任务
-    let task = current_task().unwrap();
+/// waitpid的options: 没有可以回收的子进程时立即返回
+const WNOHANG: usize = 1;
 
-    // 当pid == -1时，等待任意一个子进程即可
-    let mut inner = task.inner_exclusive_access();
-    if !inner
-        .child
-        .iter()
-        .any(|p| p.get_pid() == pid as usize || pid == -1)
-    {
-        return -1;
-        // release current tcb
-    }
+/// sys_waitpid
+/// 没有可以回收的子进程时阻塞, 除非设置了WNOHANG, 此时返回0
+/// 等待期间被信号打断时返回EINTR
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_waitpid", current_process().pid.0);
+
+    // 获取当前进程
+    let process = current_process();
+
+    let mut ret = 0;
+    let mut zombie = None;
+    let finished = process.wait_child.wait_until_interruptible(|| {
+        // 当pid == -1时，等待任意一个子进程即可
+        let mut inner = process.inner_exclusive_access();
+        // 其他线程结束了进程, 返回用户态之前当前线程也会退出
+        if inner.exiting {
+            return true;
+        }
+        if !inner
+            .child
+            .iter()
+            .any(|p| p.get_pid() == pid as usize || pid == -1)
+        {
+            ret = Errno::ECHILD.into();
+            return true;
+        }
 
-    let pair = inner.child.iter().enumerate().find(|(_, p)| {
-        p.inner_exclusive_access().is_zombie() && (pid == -1 || p.get_pid() == pid as usize)
+        // 最后退出的线程可能还没有完成切换, 它的内核栈在Processor释放引用以后才会回收
+        let idx = inner.child.iter().position(|p| {
+            p.inner_exclusive_access().is_zombie && (pid == -1 || p.get_pid() == pid as usize)
+        });
+
+        match idx {
+            Some(idx) => {
+                zombie = Some(inner.child.remove(idx));
+                true
+            }
+            // 没有进程可以回收
+            None if options & WNOHANG != 0 => true,
+            None => false,
+        }
     });
+    if !finished {
+        return Errno::EINTR.into();
+    }
 
-    if let Some((idx, _)) = pair {
-        // 这里就是清除资源
-        let child = inner.child.remove(idx);
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.get_pid();
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // 将exit_code写入到进程数据中
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
-    } else {
-        // 没有进程可以回收
-        -2
+    // 这里就是清除资源, 在等待队列的锁之外释放子进程
+    let child = match zombie {
+        Some(child) => child,
+        None => return ret,
+    };
+    let found_pid = child.get_pid();
+    let exit_code = child.inner_exclusive_access().exit_code;
+    drop(child);
+
+    // 将exit_code写入到进程数据中, 子进程已经回收, 地址无效时只返回错误
+    let exit_code_ptr = UserPtr::new(exit_code_ptr);
+    if !exit_code_ptr.is_null() {
+        if let Err(errno) = exit_code_ptr.write(&exit_code) {
+            return errno.into();
+        }
     }
+    found_pid as isize
 }
 
 /// change data segment size
 pub fn sys_sbrk(size: i32) -> isize {
     trace!(
         "[Kernel] pid[{}] sys_sbrk args[0] = {:x}",
-        current_task().unwrap().pid.0,
+        current_process().pid.0,
         size
     );
 
-    if let Some(old_brk) = current_task().unwrap().change_program_brk(size) {
+    if let Some(old_brk) = current_process().change_program_brk(size) {
         old_brk as isize
     } else {
-        -1
+        Errno::ENOMEM.into()
     }
 }
 
 /// get pid
 pub fn sys_getpid() -> isize {
-    trace!("[Kernel] pid[{}] sys_getpid", current_task().unwrap().pid.0);
+    trace!("[Kernel] pid[{}] sys_getpid", current_process().pid.0);
 
-    current_task().unwrap().pid.0 as isize
+    current_process().pid.0 as isize
 }
 
 /// sys_spawn
 #[allow(unused)]
 pub fn sys_spawn1(path: *const u8) -> isize {
-    trace!("[Kernel] pid[{}] sys_spawn", current_task().unwrap().pid.0);
-
-    let token = current_user_token();
-    let path_name = translated_str(token, path);
+    trace!("[Kernel] pid[{}] sys_spawn", current_process().pid.0);
 
-    if let Some(inode) = open_file(path_name.as_str(), OpenFlags::RDONLY) {
+    if let Ok(inode) = open_executable(path) {
         // 此时有这个app 需要检查进程池和内存是否足够分配
         inode.dump_metadata();
         let elf_data = inode.read_all();
 
         if elf_data.len() == 0 {
             println!("len = 0 ffuucckk");
-            return -1;
+            return Errno::ENOENT.into();
         }
-        let new_task_tcb = Arc::new(TaskControlBlock::new(elf_data.as_slice()));
-        let new_pid = new_task_tcb.pid.0;
+        let new_process = ProcessControlBlock::new(elf_data.as_slice());
+        let new_pid = new_process.pid.0;
         // 当前的父进程
-        let current_task_tcb = current_task().unwrap();
-        let mut current_task_inner = current_task_tcb.inner_exclusive_access();
+        let current_process = current_process();
+        let mut current_process_inner = current_process.inner_exclusive_access();
 
         // 配置父子关系
-        let mut new_task_tcb_inner = new_task_tcb.inner_exclusive_access();
-        new_task_tcb_inner.parent = current_task().as_ref().map(|arc| Arc::downgrade(arc));
+        let mut new_process_inner = new_process.inner_exclusive_access();
+        new_process_inner.parent = Some(Arc::downgrade(&current_process));
+        let new_task = new_process_inner.get_task(0).unwrap();
 
-        current_task_inner.child.push(new_task_tcb.clone());
+        current_process_inner.child.push(new_process.clone());
         // spawn调用成功！
         // 返回子进程id
-        drop(current_task_inner);
-        drop(new_task_tcb_inner);
-        add_task(new_task_tcb);
+        drop(current_process_inner);
+        drop(new_process_inner);
+        add_task(new_task);
 
         new_pid as isize
     } else {
         // 没有这个APP 直接返回即可
-        -1
+        Errno::ENOENT.into()
     }
 }
 
 #[allow(unused)]
 pub fn sys_spawn(path: *const u8) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
-        let task = current_task().unwrap();
-        let new_task = task.spwan(all_data.as_slice());
-        let new_pid = new_task.pid.0;
-        // let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
-        // // we do not have to move to next instruction since we have done it before
-        // // for child process, fork returns 0
-        // trap_cx.x[10] = 0;
-        // add new task to scheduler
-        add_task(new_task);
-        new_pid as isize
-    } else {
-        -1
+    let app_inode = match open_executable(path) {
+        Ok(app_inode) => app_inode,
+        Err(errno) => return errno.into(),
+    };
+    let all_data = app_inode.read_all();
+    let process = current_process();
+    let new_process = process.spwan(all_data.as_slice());
+    let new_pid = new_process.pid.0;
+    let new_task = new_process.inner_exclusive_access().get_task(0).unwrap();
+    // let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
+    // // we do not have to move to next instruction since we have done it before
+    // // for child process, fork returns 0
+    // trap_cx.x[10] = 0;
+    // add new task to scheduler
+    add_task(new_task);
+    new_pid as isize
+}
+
+/// 向pid发送信号signum, signum为0时只检查进程是否存在
+pub fn sys_kill(pid: isize, signum: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_kill pid = {}, signum = {}",
+        current_process().pid.0,
+        pid,
+        signum
+    );
+
+    // 不支持进程组
+    if pid <= 0 {
+        return Errno::EINVAL.into();
+    }
+    let process = match pid2process(pid as usize) {
+        Some(process) => process,
+        None => return Errno::ESRCH.into(),
+    };
+    if signum == 0 {
+        return 0;
+    }
+
+    match SignalFlags::from_signum(signum) {
+        Some(signal) => {
+            process.send_signal(signal);
+            0
+        }
+        None => Errno::EINVAL.into(),
     }
 }
 
-/// set prio
-pub fn sys_set_prio(prio: isize) -> isize {
+/// 设置signum的处理方式, old_action不为空时写入原来的处理方式
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
     trace!(
-        "[Kernel] pid[{}] sys_set_prio",
-        current_task().unwrap().pid.0
+        "[Kernel] pid[{}] sys_sigaction signum = {}",
+        current_process().pid.0,
+        signum
     );
 
+    let signal = match SignalFlags::from_signum(signum) {
+        Some(signal) => signal,
+        None => return Errno::EINVAL.into(),
+    };
+    let process = current_process();
+
+    let old_action = UserPtr::new(old_action);
+    if !old_action.is_null() {
+        let old = process.inner_exclusive_access().signal_actions.table[signum];
+        if let Err(errno) = old_action.write(&old) {
+            return errno.into();
+        }
+    }
+
+    let action = UserPtr::new(action);
+    if !action.is_null() {
+        if SignalFlags::unblockable().contains(signal) {
+            return Errno::EINVAL.into();
+        }
+        let mut new = match action.read() {
+            Ok(new) => new,
+            Err(errno) => return errno.into(),
+        };
+        new.mask = SignalFlags::from_bits_truncate(new.mask.bits()) - SignalFlags::unblockable();
+        process.inner_exclusive_access().signal_actions.table[signum] = new;
+    }
+
+    0
+}
+
+/// sigprocmask的how
+const SIG_BLOCK: usize = 0;
+const SIG_UNBLOCK: usize = 1;
+const SIG_SETMASK: usize = 2;
+
+/// 修改信号屏蔽字, old_set不为空时写入原来的屏蔽字
+pub fn sys_sigprocmask(how: usize, set: *const u64, old_set: *mut u64) -> isize {
+    trace!("[Kernel] pid[{}] sys_sigprocmask", current_process().pid.0);
+
+    let task = current_task().unwrap();
+    let old = task.inner_exclusive_access().signal_mask;
+    let old_set = UserPtr::new(old_set);
+    if !old_set.is_null() {
+        if let Err(errno) = old_set.write(&old.bits()) {
+            return errno.into();
+        }
+    }
+    let set = UserPtr::new(set);
+    if set.is_null() {
+        return 0;
+    }
+
+    let set = match set.read() {
+        Ok(set) => SignalFlags::from_bits_truncate(set),
+        Err(errno) => return errno.into(),
+    };
+    let mut inner = task.inner_exclusive_access();
+    let mask = match how {
+        SIG_BLOCK => inner.signal_mask | set,
+        SIG_UNBLOCK => inner.signal_mask - set,
+        SIG_SETMASK => set,
+        _ => return Errno::EINVAL.into(),
+    };
+    // SIGKILL和SIGSTOP不能被屏蔽
+    inner.signal_mask = mask - SignalFlags::unblockable();
+    0
+}
+
+/// 从信号处理函数返回, 恢复投递信号时保存在用户栈上的上下文
+pub fn sys_sigreturn() -> isize {
+    trace!("[Kernel] pid[{}] sys_sigreturn", current_process().pid.0);
+
+    // 处理函数返回以后, sp指向投递时保存的SignalFrame
+    let trap_cx = current_trap_cx();
+    let frame = match UserPtr::new(trap_cx.x[2] as *const SignalFrame).read() {
+        Ok(frame) => frame,
+        Err(errno) => return errno.into(),
+    };
+
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().signal_mask =
+        SignalFlags::from_bits_truncate(frame.mask.bits()) - SignalFlags::unblockable();
+    trap_cx.x = frame.x;
+    trap_cx.sepc = frame.sepc;
+    // 返回值会写入a0, 保持被打断时的值
+    trap_cx.x[10] as isize
+}
+
+/// set prio
+/// 超过MAX_PRIO时取MAX_PRIO, 返回实际设置的优先级
+pub fn sys_set_prio(prio: isize) -> isize {
+    trace!("[Kernel] pid[{}] sys_set_prio", current_process().pid.0);
+
     if prio <= 1 {
-        return -1;
+        return Errno::EINVAL.into();
     }
 
-    current_task().unwrap().inner_exclusive_access().prio = prio as usize;
+    let prio = (prio as usize).min(MAX_PRIO);
+    current_task().unwrap().inner_exclusive_access().prio = prio;
 
     prio as isize
 }
+
+/// 切换所有核心使用的调度算法
+/// 0: stride, 1: 时间片轮转, 2: 多级反馈队列, 3: CFS
+pub fn sys_set_scheduler(policy: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_set_scheduler policy = {}",
+        current_process().pid.0,
+        policy
+    );
+
+    match SchedPolicy::from_id(policy) {
+        Some(policy) => {
+            set_sched_policy(policy);
+            0
+        }
+        None => Errno::EINVAL.into(),
+    }
+}
@@ -0,0 +1,337 @@
+//! Syscall: 用户程序使用的互斥锁, 信号量和条件变量
+//! 这些对象属于进程, 通过创建时返回的id索引
+
+use super::process::TimeSpec;
+use super::uaccess::UserPtr;
+use crate::config::PAGE_SIZE;
+use crate::errno::Errno;
+use crate::mm::{Page, PhysAddr, VirtAddr};
+use crate::sync::{futex_wait, futex_wake, Condvar, FutexKey, Resource, Semaphore, UserMutex};
+use crate::task::{current_process, current_task};
+use crate::timer::get_time;
+use alloc::sync::Arc;
+use core::mem::size_of;
+
+/// 阻塞直到futex_wake, 用户内存中的值不等于val时返回EAGAIN
+const FUTEX_WAIT: usize = 0;
+/// 唤醒最多val个等待者
+const FUTEX_WAKE: usize = 1;
+/// 只在进程内使用, 即使位于MAP_SHARED区域也以虚拟地址为键
+const FUTEX_PRIVATE_FLAG: usize = 128;
+
+/// 创建互斥锁, blocking为0时获取不到锁会让出CPU后重试, 否则阻塞
+/// 返回互斥锁的id
+pub fn sys_mutex_create(blocking: usize) -> isize {
+    trace!("[Kernel] pid[{}] sys_mutex_create", current_process().pid.0);
+
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let id = inner.mutex_list.len();
+    inner
+        .mutex_list
+        .push(Arc::new(UserMutex::new(blocking != 0)));
+    inner.deadlock_detector.add_resource(Resource::Mutex(id), 1);
+    id as isize
+}
+
+/// 获取互斥锁, 启用死锁检测时可能会死锁的请求返回EDEADLK
+/// 等待期间进程开始退出或者被信号打断时返回EINTR
+pub fn sys_mutex_lock(mutex_id: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_mutex_lock mutex_id = {}",
+        current_process().pid.0,
+        mutex_id
+    );
+
+    let tid = current_task().unwrap().tid();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let mutex = match inner.mutex_list.get(mutex_id) {
+        Some(mutex) => mutex.clone(),
+        None => return Errno::EINVAL.into(),
+    };
+    let res = Resource::Mutex(mutex_id);
+    if !inner.deadlock_detector.request(tid, res) {
+        return Errno::EDEADLK.into();
+    }
+    drop(inner);
+
+    if !mutex.lock(|| process.inner_exclusive_access().exiting) {
+        return Errno::EINTR.into();
+    }
+    process
+        .inner_exclusive_access()
+        .deadlock_detector
+        .acquire(tid, res);
+    0
+}
+
+/// 释放互斥锁, 没有上锁时返回EPERM
+pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_mutex_unlock mutex_id = {}",
+        current_process().pid.0,
+        mutex_id
+    );
+
+    let tid = current_task().unwrap().tid();
+    let process = current_process();
+    let mutex = match process.inner_exclusive_access().mutex_list.get(mutex_id) {
+        Some(mutex) => mutex.clone(),
+        None => return Errno::EINVAL.into(),
+    };
+    if !mutex.unlock() {
+        return Errno::EPERM.into();
+    }
+    process
+        .inner_exclusive_access()
+        .deadlock_detector
+        .release(tid, Resource::Mutex(mutex_id));
+    0
+}
+
+/// 创建有res_count个资源的信号量, 返回信号量的id
+pub fn sys_semaphore_create(res_count: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_semaphore_create res_count = {}",
+        current_process().pid.0,
+        res_count
+    );
+
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let id = inner.semaphore_list.len();
+    inner
+        .semaphore_list
+        .push(Arc::new(Semaphore::new(res_count)));
+    inner
+        .deadlock_detector
+        .add_resource(Resource::Semaphore(id), res_count);
+    id as isize
+}
+
+/// 释放信号量的一个资源
+pub fn sys_semaphore_up(sem_id: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_semaphore_up sem_id = {}",
+        current_process().pid.0,
+        sem_id
+    );
+
+    let tid = current_task().unwrap().tid();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let sem = match inner.semaphore_list.get(sem_id) {
+        Some(sem) => sem.clone(),
+        None => return Errno::EINVAL.into(),
+    };
+    inner
+        .deadlock_detector
+        .release(tid, Resource::Semaphore(sem_id));
+    drop(inner);
+
+    sem.up();
+    0
+}
+
+/// 获取信号量的一个资源, 启用死锁检测时可能会死锁的请求返回EDEADLK
+/// 等待期间进程开始退出或者被信号打断时返回EINTR
+pub fn sys_semaphore_down(sem_id: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_semaphore_down sem_id = {}",
+        current_process().pid.0,
+        sem_id
+    );
+
+    let tid = current_task().unwrap().tid();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let sem = match inner.semaphore_list.get(sem_id) {
+        Some(sem) => sem.clone(),
+        None => return Errno::EINVAL.into(),
+    };
+    let res = Resource::Semaphore(sem_id);
+    if !inner.deadlock_detector.request(tid, res) {
+        return Errno::EDEADLK.into();
+    }
+    drop(inner);
+
+    if !sem.down(|| process.inner_exclusive_access().exiting) {
+        return Errno::EINTR.into();
+    }
+    process
+        .inner_exclusive_access()
+        .deadlock_detector
+        .acquire(tid, res);
+    0
+}
+
+/// 创建条件变量, 返回条件变量的id
+pub fn sys_condvar_create() -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_condvar_create",
+        current_process().pid.0
+    );
+
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let id = inner.condvar_list.len();
+    inner.condvar_list.push(Arc::new(Condvar::new()));
+    id as isize
+}
+
+/// 唤醒一个等待条件变量的线程
+pub fn sys_condvar_signal(condvar_id: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_condvar_signal condvar_id = {}",
+        current_process().pid.0,
+        condvar_id
+    );
+
+    let process = current_process();
+    let condvar = match process
+        .inner_exclusive_access()
+        .condvar_list
+        .get(condvar_id)
+    {
+        Some(condvar) => condvar.clone(),
+        None => return Errno::EINVAL.into(),
+    };
+    condvar.signal();
+    0
+}
+
+/// 释放互斥锁并等待条件变量, 返回之前重新获取互斥锁
+/// 等待期间进程开始退出或者被信号打断时返回EINTR, 此时不持有互斥锁
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_condvar_wait condvar_id = {}, mutex_id = {}",
+        current_process().pid.0,
+        condvar_id,
+        mutex_id
+    );
+
+    let tid = current_task().unwrap().tid();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let (condvar, mutex) = match (
+        inner.condvar_list.get(condvar_id),
+        inner.mutex_list.get(mutex_id),
+    ) {
+        (Some(condvar), Some(mutex)) => (condvar.clone(), mutex.clone()),
+        _ => return Errno::EINVAL.into(),
+    };
+    drop(inner);
+
+    // 等待期间不持有互斥锁
+    let res = Resource::Mutex(mutex_id);
+    process
+        .inner_exclusive_access()
+        .deadlock_detector
+        .release(tid, res);
+    if !condvar.wait(&mutex, || process.inner_exclusive_access().exiting) {
+        return Errno::EINTR.into();
+    }
+    process
+        .inner_exclusive_access()
+        .deadlock_detector
+        .acquire(tid, res);
+    0
+}
+
+/// enabled为1时启用死锁检测, 为0时关闭
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_enable_deadlock_detect enabled = {}",
+        current_process().pid.0,
+        enabled
+    );
+
+    let enabled = match enabled {
+        0 => false,
+        1 => true,
+        _ => return Errno::EINVAL.into(),
+    };
+    current_process()
+        .inner_exclusive_access()
+        .deadlock_detector
+        .enabled = enabled;
+    0
+}
+
+/// 用户内存uaddr处的u32对应的futex键
+/// MAP_SHARED区域中的共享futex以物理地址为键, 同时返回已经固定在内存中的页面, 使用完以后需要unpin
+/// 其他futex只在进程内可见, 以虚拟地址为键, 不受换出和写时复制影响
+fn futex_key(uaddr: usize, private: bool) -> Result<(FutexKey, Option<Arc<Page>>), Errno> {
+    if uaddr % size_of::<u32>() != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let memory_set = &mut inner.memory_set;
+    if !memory_set.access_user(uaddr, size_of::<u32>(), false, |_| {}) {
+        return Err(Errno::EFAULT);
+    }
+    if !private {
+        if let Some(page) = memory_set.shared_page(VirtAddr::from(uaddr)) {
+            let pa = usize::from(PhysAddr::from(page.pin())) + uaddr % PAGE_SIZE;
+            return Ok((FutexKey::Shared(pa), Some(page)));
+        }
+    }
+    Ok((FutexKey::Private(process.pid.0, uaddr), None))
+}
+
+/// futex操作, 支持FUTEX_WAIT和FUTEX_WAKE
+/// FUTEX_WAIT: uaddr处的值等于val时阻塞, timeout不为空时最多等待这么长时间, 超时返回ETIMEDOUT
+/// 被信号打断或者进程开始退出时返回EINTR
+/// FUTEX_WAKE: 唤醒最多val个等待者, 返回唤醒的数量
+pub fn sys_futex(uaddr: *const u32, op: usize, val: u32, timeout: *const TimeSpec) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_futex uaddr = {:#x}, op = {}, val = {}",
+        current_process().pid.0,
+        uaddr as usize,
+        op,
+        val
+    );
+
+    let cmd = op & !FUTEX_PRIVATE_FLAG;
+    if cmd != FUTEX_WAIT && cmd != FUTEX_WAKE {
+        return Errno::ENOSYS.into();
+    }
+    let deadline = if cmd == FUTEX_WAIT && !timeout.is_null() {
+        match UserPtr::new(timeout)
+            .read()
+            .and_then(|timeout| timeout.to_ticks())
+        {
+            Ok(ticks) => Some(get_time().saturating_add(ticks)),
+            Err(errno) => return errno.into(),
+        }
+    } else {
+        None
+    };
+
+    let (key, page) = match futex_key(uaddr as usize, op & FUTEX_PRIVATE_FLAG != 0) {
+        Ok(key) => key,
+        Err(errno) => return errno.into(),
+    };
+
+    let ret = if cmd == FUTEX_WAIT {
+        let check = || {
+            if UserPtr::new(uaddr).read()? != val {
+                return Err(Errno::EAGAIN);
+            }
+            Ok(())
+        };
+        match futex_wait(key, check, deadline) {
+            Ok(()) => 0,
+            Err(errno) => errno.into(),
+        }
+    } else {
+        futex_wake(key, val as usize) as isize
+    };
+
+    if let Some(page) = page {
+        page.unpin();
+    }
+    ret
+}
@@ -0,0 +1,102 @@
+//! Syscall: 线程的创建与回收
+
+use crate::errno::Errno;
+use crate::mm::KERNEL_SPACE;
+use crate::task::{add_task, current_process, current_task, TaskControlBlock};
+use crate::trap::{trap_handler, TrapContext};
+
+/// 在当前进程中创建线程, 从entry开始执行, arg通过a0传入
+/// 返回新线程的tid
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_thread_create entry = {:#x}",
+        current_process().pid.0,
+        entry
+    );
+
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let mut process_inner = process.inner_exclusive_access();
+    let new_task = TaskControlBlock::new(&process, &mut process_inner, true);
+    drop(process_inner);
+
+    // 继承信号屏蔽字和优先级, 继承vruntime避免通过创建线程获得更多的CPU时间
+    let task_inner = task.inner_exclusive_access();
+    let mut new_task_inner = new_task.inner_exclusive_access();
+    new_task_inner.signal_mask = task_inner.signal_mask;
+    new_task_inner.prio = task_inner.prio;
+    new_task_inner.vruntime = task_inner.vruntime;
+    drop(task_inner);
+
+    let res = new_task_inner.res.as_ref().unwrap();
+    let new_tid = res.tid;
+    let trap_cx = new_task_inner.get_trap_cx();
+    *trap_cx = TrapContext::app_init_context(
+        entry,
+        res.ustack_top().unwrap(),
+        KERNEL_SPACE.lock().token(),
+        new_task.kernel_stack.get_top(),
+        trap_handler as usize,
+    );
+    trap_cx.x[10] = arg;
+    drop(new_task_inner);
+
+    add_task(new_task);
+    new_tid as isize
+}
+
+/// 当前线程的tid
+pub fn sys_gettid() -> isize {
+    trace!("[Kernel] pid[{}] sys_gettid", current_process().pid.0);
+
+    current_task().unwrap().tid() as isize
+}
+
+/// 等待tid退出并回收, 返回它的exit_code
+/// 线程不存在或者已经被回收时返回ESRCH, 等待期间被信号打断时返回EINTR
+pub fn sys_waittid(tid: usize) -> isize {
+    trace!(
+        "[Kernel] pid[{}] sys_waittid tid = {}",
+        current_process().pid.0,
+        tid
+    );
+
+    let task = current_task().unwrap();
+    if task.tid() == tid {
+        return Errno::EDEADLK.into();
+    }
+
+    let process = task.process.upgrade().unwrap();
+    let mut ret = 0;
+    let mut exited = None;
+    let finished = process.wait_thread.wait_until_interruptible(|| {
+        let mut inner = process.inner_exclusive_access();
+        // 进程正在退出, 返回用户态之前当前线程也会退出
+        if inner.exiting {
+            return true;
+        }
+        let waited = match inner.get_task(tid) {
+            Some(waited) => waited,
+            None => {
+                ret = Errno::ESRCH.into();
+                return true;
+            }
+        };
+        let exit_code = waited.inner_exclusive_access().exit_code;
+        match exit_code {
+            Some(exit_code) => {
+                ret = exit_code as isize;
+                exited = inner.remove_task(tid);
+                true
+            }
+            None => false,
+        }
+    });
+    if !finished {
+        return Errno::EINTR.into();
+    }
+
+    // 在等待队列的锁之外释放线程
+    drop(exited);
+    ret
+}
@@ -0,0 +1,165 @@
+//! 系统调用访问当前任务的用户内存
+//! 每一页都检查映射和U/R/W权限, 地址无效时返回EFAULT而不是让内核崩溃
+
+use crate::config::PAGE_SIZE;
+use crate::errno::Errno;
+use crate::mm::UserBuffer;
+use crate::task::current_process;
+use alloc::string::String;
+use alloc::vec;
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+
+/// 路径等字符串参数的最大长度, 包括结尾的0
+pub const PATH_MAX: usize = 4096;
+
+/// 文件读写时每次经过内核缓冲区复制的最大长度
+const BOUNCE_SIZE: usize = 4 * PAGE_SIZE;
+
+/// 从用户内存src处复制dst.len()字节
+pub fn copy_from_user(dst: &mut [u8], src: usize) -> Result<(), Errno> {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.memory_set.read_user(src, dst) {
+        Ok(())
+    } else {
+        Err(Errno::EFAULT)
+    }
+}
+
+/// 将src复制到用户内存dst处
+pub fn copy_to_user(dst: usize, src: &[u8]) -> Result<(), Errno> {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.memory_set.write_user(dst, src) {
+        Ok(())
+    } else {
+        Err(Errno::EFAULT)
+    }
+}
+
+/// 从用户内存复制以0结尾的字符串
+pub fn copy_str_from_user(ptr: *const u8) -> Result<String, Errno> {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    inner.memory_set.read_user_str(ptr as usize, PATH_MAX - 1)
+}
+
+/// 检查用户缓冲区[ptr, ptr + len)可以访问, 同时完成缺页处理
+/// write表示内核会写入缓冲区, 即文件的read
+pub fn check_user_buffer(ptr: *const u8, len: usize, write: bool) -> Result<(), Errno> {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner
+        .memory_set
+        .access_user(ptr as usize, len, write, |_| {})
+    {
+        Ok(())
+    } else {
+        Err(Errno::EFAULT)
+    }
+}
+
+/// 将内核缓冲区交给文件读写, 文件读写返回之前buf一直有效
+fn kernel_buffer(buf: &mut [u8]) -> UserBuffer {
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+    UserBuffer::new(vec![buf])
+}
+
+/// 通过内核缓冲区将文件读入用户内存[ptr, ptr + len), 返回读入的字节数
+/// 读文件时可能睡眠, 期间用户页面可能被换出或者取消映射, 因此不直接读入用户页面
+/// read每次读入一块, 参数为之前已经读入的字节数
+/// more为false时只读一次, 否则直到读入的数据不满一块, 用于不会阻塞的普通文件
+pub fn read_to_user<F>(ptr: *const u8, len: usize, more: bool, mut read: F) -> Result<usize, Errno>
+where
+    F: FnMut(usize, UserBuffer) -> Result<usize, Errno>,
+{
+    // 读取会消耗管道中的数据, 因此先检查缓冲区
+    check_user_buffer(ptr, len, true)?;
+    let mut bounce = vec![0u8; len.min(BOUNCE_SIZE)];
+    let mut done = 0;
+    while done < len {
+        let count = (len - done).min(bounce.len());
+        let read_len = match read(done, kernel_buffer(&mut bounce[..count])) {
+            Ok(read_len) => read_len,
+            Err(errno) if done == 0 => return Err(errno),
+            Err(_) => break,
+        };
+        // 其他线程可能在读文件期间取消了映射
+        if let Err(errno) = copy_to_user(ptr as usize + done, &bounce[..read_len]) {
+            return if done == 0 { Err(errno) } else { Ok(done) };
+        }
+        done += read_len;
+        if read_len < count || !more {
+            break;
+        }
+    }
+    Ok(done)
+}
+
+/// 通过内核缓冲区将用户内存[ptr, ptr + len)写入文件, 返回写入的字节数
+/// write每次写入一块, 参数为之前已经写入的字节数, 写入的数据不满一块时结束
+pub fn write_from_user<F>(ptr: *const u8, len: usize, mut write: F) -> Result<usize, Errno>
+where
+    F: FnMut(usize, UserBuffer) -> Result<usize, Errno>,
+{
+    check_user_buffer(ptr, len, false)?;
+    let mut bounce = vec![0u8; len.min(BOUNCE_SIZE)];
+    let mut done = 0;
+    while done < len {
+        let count = (len - done).min(bounce.len());
+        if let Err(errno) = copy_from_user(&mut bounce[..count], ptr as usize + done) {
+            return if done == 0 { Err(errno) } else { Ok(done) };
+        }
+        let written = match write(done, kernel_buffer(&mut bounce[..count])) {
+            Ok(written) => written,
+            Err(errno) if done == 0 => return Err(errno),
+            Err(_) => break,
+        };
+        done += written;
+        if written < count {
+            break;
+        }
+    }
+    Ok(done)
+}
+
+/// 指向用户内存中一个T的指针
+pub struct UserPtr<T> {
+    ptr: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> UserPtr<T> {
+    /// 包装用户传入的指针
+    pub fn new(ptr: *const T) -> Self {
+        Self {
+            ptr: ptr as usize,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 可选的参数为空时不读写
+    pub fn is_null(&self) -> bool {
+        self.ptr == 0
+    }
+
+    /// 将value写入用户内存
+    pub fn write(&self, value: &T) -> Result<(), Errno> {
+        let src =
+            unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+        copy_to_user(self.ptr, src)
+    }
+}
+
+impl<T: Copy> UserPtr<T> {
+    /// 从用户内存读取一个T, T只能是任意字节都合法的简单数据
+    pub fn read(&self) -> Result<T, Errno> {
+        let mut value = MaybeUninit::<T>::uninit();
+        let dst = unsafe {
+            core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>())
+        };
+        copy_from_user(dst, self.ptr)?;
+        Ok(unsafe { value.assume_init() })
+    }
+}
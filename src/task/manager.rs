@@ -1,60 +1,96 @@
-use core::usize::MAX;
+use crate::sync::SpinLock;
 
-use crate::sync::UPSafeCell;
-
-use super::TaskControlBlock;
+use super::scheduler::{SchedPolicy, Scheduler};
+use super::{ProcessControlBlock, TaskControlBlock};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use lazy_static::*;
 
 pub struct TaskManager {
+    /// 当前的调度算法
+    policy: SchedPolicy,
     /// 就绪队列
-    ready_queue: Vec<Arc<TaskControlBlock>>,
+    scheduler: Box<dyn Scheduler>,
 }
 
 impl TaskManager {
     pub fn new() -> Self {
+        let policy = SchedPolicy::default_policy();
         Self {
-            ready_queue: Vec::new(),
+            policy,
+            scheduler: policy.new_scheduler(),
         }
     }
 
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push(task);
+        self.scheduler.add(task);
     }
 
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        let mut min_stride = MAX;
-        let mut min_stride_idx = MAX;
-
-        for (cur_idx, res) in self.ready_queue.iter().enumerate() {
-            let current_stride = res.inner_exclusive_access().stride;
-            if min_stride > current_stride {
-                min_stride_idx = cur_idx;
-                min_stride = current_stride;
-            }
-        }
+        self.scheduler.fetch()
+    }
 
-        if min_stride_idx == MAX {
-            None
-        } else {
-            self.ready_queue[min_stride_idx].update_stride();
-            Some(self.ready_queue.remove(min_stride_idx))
+    /// 切换调度算法, 就绪的任务移到新的调度器中
+    pub fn set_policy(&mut self, policy: SchedPolicy) {
+        if policy == self.policy {
+            return;
+        }
+        let mut scheduler = policy.new_scheduler();
+        while let Some(task) = self.scheduler.fetch() {
+            scheduler.add(task);
         }
+        self.policy = policy;
+        self.scheduler = scheduler;
     }
 }
 
 lazy_static! {
-    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
-        unsafe { UPSafeCell::new(TaskManager::new()) };
+    pub static ref TASK_MANAGER: SpinLock<TaskManager> = SpinLock::new(TaskManager::new());
+    /// pid到进程的索引, 进程退出时删除
+    static ref PID2PROCESS: SpinLock<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+        SpinLock::new(BTreeMap::new());
 }
 
 /// 在就绪队列中添加Ready的进程
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().add(task);
+    TASK_MANAGER.lock().add(task);
 }
 
 /// 从就绪队列中取出一个就绪进程
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    TASK_MANAGER.exclusive_access().fetch()
+    TASK_MANAGER.lock().fetch()
+}
+
+/// 正在运行的任务经过一个时钟中断, 返回是否需要切换
+pub fn tick_task(task: &Arc<TaskControlBlock>) -> bool {
+    TASK_MANAGER.lock().scheduler.tick(task)
+}
+
+/// 切换调度算法
+pub fn set_sched_policy(policy: SchedPolicy) {
+    TASK_MANAGER.lock().set_policy(policy);
+}
+
+/// 记录新创建的进程
+pub fn insert_into_pid2process(process: &Arc<ProcessControlBlock>) {
+    PID2PROCESS
+        .lock()
+        .insert(process.get_pid(), process.clone());
+}
+
+/// 进程退出时删除
+pub fn remove_from_pid2process(pid: usize) {
+    PID2PROCESS.lock().remove(&pid);
+}
+
+/// 所有还没有退出的进程的pid, 从小到大排列
+pub fn all_pids() -> Vec<usize> {
+    PID2PROCESS.lock().keys().copied().collect()
+}
+
+/// 通过pid查找还没有退出的进程
+pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    PID2PROCESS.lock().get(&pid).cloned()
 }
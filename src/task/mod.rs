@@ -3,8 +3,10 @@
 mod context;
 mod manager;
 mod pid;
+mod process;
 mod processor;
 mod scheduler;
+mod signal;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
@@ -13,30 +15,31 @@ use crate::fs::{open_file, OpenFlags};
 use alloc::sync::Arc;
 pub use context::TaskContext;
 use lazy_static::*;
-pub use manager::add_task;
+pub use manager::{add_task, all_pids, pid2process, set_sched_policy};
+pub use process::ProcessControlBlock;
 pub use processor::{
-    current_task, current_task_info_inner, current_trap_cx, current_user_token,
-    mapping_address_space_for_current_task, run_tasks, schedule, take_current_task,
-    unmapping_address_space_for_current_task, update_current_task_syscall_times,
+    current_process, current_task, current_task_info_inner, current_trap_cx,
+    current_trap_cx_user_va, current_user_token, hart_id, page_fault_for_current_task, run_tasks,
+    schedule, unmapping_address_space_for_current_task, update_current_task_syscall_times,
 };
-pub use scheduler::BIG_STRIDE;
-pub use task::{exit_current_and_run_next, TaskControlBlock, TaskStatus};
+pub use scheduler::{SchedPolicy, BIG_STRIDE, MAX_PRIO};
+pub use signal::{handle_signals, SignalAction, SignalFlags, SignalFrame, MAX_SIG};
+pub use task::{exit_current_and_run_next, exit_group_and_run_next, TaskControlBlock, TaskStatus};
 
 lazy_static! {
     /// initproc的初始PCB
     /// INITPROC进程在全局变量区
-    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new({
-        let inode = open_file("ch6b_initproc", OpenFlags::RDONLY).unwrap();
+    pub static ref INITPROC: Arc<ProcessControlBlock> = {
+        let inode = open_file("/ch6b_initproc", OpenFlags::RDONLY).unwrap();
         let v = inode.read_all();
-        TaskControlBlock::new(v.as_slice())
-    }
-    );
+        ProcessControlBlock::new(v.as_slice())
+    };
 }
 
 /// 暂停当前进程 执行另外一个进程
 pub fn suspend_current_and_run_next() {
     // 获取当前Process上正在执行的任务
-    let task = take_current_task().unwrap();
+    let task = current_task().unwrap();
 
     let mut task_inner = task.inner_exclusive_access();
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
@@ -44,16 +47,59 @@ pub fn suspend_current_and_run_next() {
     // 还没有执行完，状态改为Ready
     task_inner.task_status = TaskStatus::Ready;
     drop(task_inner);
-
-    // 将当前任务放入任务管理器队尾
-    add_task(task);
+    drop(task);
 
     // 调用schedule函数 切换回idle进程 调用执行下一个任务
-    // 如果只有一个任务，那么将继续执行
+    // idle控制流会将当前任务放入任务管理器队尾
+    schedule(task_cx_ptr);
+}
+
+/// 当前任务经过一个时钟中断, 由调度算法决定是否切换到其他任务
+pub fn tick_current_and_run_next() {
+    let task = current_task().unwrap();
+    if manager::tick_task(&task) {
+        drop(task);
+        suspend_current_and_run_next();
+    }
+}
+
+/// 阻塞当前任务, 执行另外一个任务
+/// 调用前需要在持有等待队列的锁时将状态改为Blocked, 这样之后的唤醒不会丢失
+/// 如果在切换之前已经被唤醒, 相当于一次yield
+pub fn block_current_and_run_next() {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    drop(task_inner);
+    drop(task);
+
     schedule(task_cx_ptr);
 }
 
+/// 当前任务是否被信号打断, 可中断的等待应当放弃并返回EINTR
+pub fn current_interrupted() -> bool {
+    current_task().unwrap().inner_exclusive_access().interrupted
+}
+
+/// 唤醒一个被阻塞的任务
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    // 还没有完成切换的任务由idle控制流放回就绪队列
+    let parked = core::mem::take(&mut task_inner.parked);
+    drop(task_inner);
+
+    if parked {
+        add_task(task);
+    }
+}
+
 /// add init process to the task manager
 pub fn add_initproc() {
-    add_task(INITPROC.clone());
+    manager::insert_into_pid2process(&INITPROC);
+    let task = INITPROC.inner_exclusive_access().get_task(0).unwrap();
+    add_task(task);
 }
@@ -1,9 +1,8 @@
-//! PID Handle
+//! PID Handle, 内核栈以及线程资源的编号分配
 
 use crate::{
-    config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE},
-    mm::{MapPermission, VirtAddr, KERNEL_SPACE},
-    sync::UPSafeCell,
+    mm::{kernel_stack_position, MapPermission, VirtAddr, KERNEL_SPACE},
+    sync::SpinLock,
 };
 use alloc::vec::Vec;
 use lazy_static::*;
@@ -11,84 +10,83 @@ use lazy_static::*;
 /// pid_t
 pub struct PidHandle(pub usize);
 
-struct PidAllocator {
+/// 编号分配器, 优先复用回收的编号
+/// 用于pid, 内核栈以及进程内的线程编号
+pub struct RecycleAllocator {
     current: usize,
     recycled: Vec<usize>,
 }
 
-impl PidAllocator {
-    /// 创建一个新的Pid Allocator
+impl RecycleAllocator {
+    /// 创建一个新的分配器
     pub fn new() -> Self {
-        PidAllocator {
+        RecycleAllocator {
             current: 0,
             recycled: Vec::new(),
         }
     }
 
-    /// 分配PID
-    pub fn alloc(&mut self) -> PidHandle {
-        if let Some(pid) = self.recycled.pop() {
-            PidHandle(pid)
+    /// 分配编号
+    pub fn alloc(&mut self) -> usize {
+        if let Some(id) = self.recycled.pop() {
+            id
         } else {
             self.current += 1;
-            PidHandle(self.current - 1)
+            self.current - 1
         }
     }
 
-    /// 回收Pid
-    pub fn dealloc(&mut self, pid: usize) {
-        assert!(pid < self.current);
+    /// 回收编号
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
         assert!(
-            self.recycled.iter().find(|ppid| **ppid == pid).is_none(),
-            "pid {} has been deallocated!",
-            pid
+            self.recycled
+                .iter()
+                .find(|recycled| **recycled == id)
+                .is_none(),
+            "id {} has been deallocated!",
+            id
         );
 
-        self.recycled.push(pid);
+        self.recycled.push(id);
     }
 }
 
 lazy_static! {
-    static ref PID_ALLOCATOR: UPSafeCell<PidAllocator> =
-        unsafe { UPSafeCell::new(PidAllocator::new()) };
+    static ref PID_ALLOCATOR: SpinLock<RecycleAllocator> = SpinLock::new(RecycleAllocator::new());
+    static ref KSTACK_ALLOCATOR: SpinLock<RecycleAllocator> =
+        SpinLock::new(RecycleAllocator::new());
 }
 
 /// 公开的PID分配接口
 pub fn pid_alloc() -> PidHandle {
-    PID_ALLOCATOR.exclusive_access().alloc()
+    PidHandle(PID_ALLOCATOR.lock().alloc())
 }
 
 impl Drop for PidHandle {
     fn drop(&mut self) {
-        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+        PID_ALLOCATOR.lock().dealloc(self.0);
     }
 }
 
-pub struct KernelStack {
-    /// 当前KernelStack对应的Pid
-    pid: usize,
-}
-
-pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
-    let top = TRAMPOLINE - app_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
-    let bottom = top - KERNEL_STACK_SIZE;
-    (bottom, top)
+/// 线程的内核栈, 编号与pid无关
+/// 内核栈释放以后编号才会被复用, 因此不会有两个线程同时使用同一块内核栈
+pub struct KernelStack(usize);
+
+/// 分配一个内核栈并映射到内核地址空间
+pub fn kstack_alloc() -> KernelStack {
+    let kstack_id = KSTACK_ALLOCATOR.lock().alloc();
+    let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(kstack_id);
+    KERNEL_SPACE.lock().insert_framed_area(
+        kernel_stack_bottom.into(),
+        kernel_stack_top.into(),
+        MapPermission::R | MapPermission::W,
+    );
+
+    KernelStack(kstack_id)
 }
 
 impl KernelStack {
-    // 根据PID 分配内核栈KernelStack
-    pub fn new(pid_handle: &PidHandle) -> Self {
-        let pid = pid_handle.0;
-        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
-        KERNEL_SPACE.exclusive_access().insert_framed_area(
-            kernel_stack_bottom.into(),
-            kernel_stack_top.into(),
-            MapPermission::R | MapPermission::W,
-        );
-
-        KernelStack { pid: pid_handle.0 }
-    }
-
     // where表示泛型T必须实现Sized trait
     /// 将一个泛型T放入栈顶
     pub fn push_on_top<T>(&self, value: T) -> *mut T
@@ -105,7 +103,7 @@ impl KernelStack {
 
     /// 获取栈顶
     pub fn get_top(&self) -> usize {
-        let (_, kernel_stack_top) = kernel_stack_position(self.pid);
+        let (_, kernel_stack_top) = kernel_stack_position(self.0);
         kernel_stack_top
     }
 }
@@ -113,10 +111,11 @@ impl KernelStack {
 // 当KernelStack会回收时，将该KernelStack从Kernel Address Space中删除
 impl Drop for KernelStack {
     fn drop(&mut self) {
-        let (kernel_stack_bottom, _) = kernel_stack_position(self.pid);
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.0);
         let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
         KERNEL_SPACE
-            .exclusive_access()
+            .lock()
             .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        KSTACK_ALLOCATOR.lock().dealloc(self.0);
     }
 }
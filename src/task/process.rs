@@ -0,0 +1,462 @@
+//! Process: 进程持有地址空间, 打开文件表等资源, 由一个或多个线程执行
+
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::current_task;
+use super::manager::insert_into_pid2process;
+use super::pid::{pid_alloc, PidHandle, RecycleAllocator};
+use super::signal::{SignalActions, SignalFlags};
+use super::task::{TaskControlBlock, TaskInfoInner, TaskStatus, TaskUserRes};
+use crate::config::MAX_FD_NUM;
+use crate::errno::Errno;
+use crate::fs::{FileDescriptor, Stdin, Stdout};
+use crate::mm::{MapPermission, MemorySet, MmapFile, VirtAddr, KERNEL_SPACE};
+use crate::sync::{
+    Condvar, DeadlockDetector, Semaphore, SpinLock, SpinLockGuard, UserMutex, WaitQueue,
+};
+use crate::trap::{trap_handler, TrapContext};
+
+/// struct of PCB
+pub struct ProcessControlBlock {
+    // immutable
+    /// Pid
+    pub pid: PidHandle,
+    /// 等待子进程退出的队列
+    pub wait_child: WaitQueue,
+    /// 等待线程退出的队列
+    pub wait_thread: WaitQueue,
+    /// 被暂停的线程在这里等待SIGCONT
+    pub wait_continue: WaitQueue,
+    // mutable
+    /// 可变信息
+    inner: SpinLock<ProcessControlBlockInner>,
+}
+
+/// struct of PCB inner
+pub struct ProcessControlBlockInner {
+    /// 所有线程都已经退出, 等待父进程回收
+    pub is_zombie: bool,
+    /// 进程正在退出, 其余线程返回用户态之前退出
+    pub exiting: bool,
+    /// address space
+    pub memory_set: MemorySet,
+    /// 父进程
+    // Weak不会影响父进程的引用计数
+    pub parent: Option<Weak<ProcessControlBlock>>,
+    /// 子进程
+    pub child: Vec<Arc<ProcessControlBlock>>,
+    /// 进程退出code
+    pub exit_code: i32,
+    /// task info inner
+    pub task_info_inner: TaskInfoInner,
+    /// heap bottom
+    pub heap_bottom: usize,
+    /// program break
+    pub program_brk: usize,
+    /// 打开文件表
+    pub fd_table: Vec<Option<FileDescriptor>>,
+    /// 当前工作目录, 规范的绝对路径
+    pub cwd: String,
+    /// 等待处理的信号
+    pub signals: SignalFlags,
+    /// 每个信号的处理方式
+    pub signal_actions: SignalActions,
+    /// 被暂停信号暂停, 等待SIGCONT
+    pub stopped: bool,
+    /// 线程, 通过tid索引, 被回收以后为None
+    pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    /// tid分配器
+    task_res_allocator: RecycleAllocator,
+    /// 用户程序创建的互斥锁, 通过id索引
+    pub mutex_list: Vec<Arc<UserMutex>>,
+    /// 用户程序创建的信号量, 通过id索引
+    pub semaphore_list: Vec<Arc<Semaphore>>,
+    /// 用户程序创建的条件变量, 通过id索引
+    pub condvar_list: Vec<Arc<Condvar>>,
+    /// 互斥锁和信号量的死锁检测
+    pub deadlock_detector: DeadlockDetector,
+}
+
+impl ProcessControlBlock {
+    /// 获取可变引用
+    pub fn inner_exclusive_access(&self) -> SpinLockGuard<'_, ProcessControlBlockInner> {
+        self.inner.lock()
+    }
+
+    /// 获取PID
+    pub fn get_pid(&self) -> usize {
+        self.pid.0
+    }
+
+    /// 创建一个还没有线程的进程
+    fn new_empty(
+        memory_set: MemorySet,
+        parent: Option<Weak<ProcessControlBlock>>,
+        heap_bottom: usize,
+        program_brk: usize,
+        fd_table: Vec<Option<FileDescriptor>>,
+        cwd: String,
+        signal_actions: SignalActions,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            pid: pid_alloc(),
+            wait_child: WaitQueue::new(),
+            wait_thread: WaitQueue::new(),
+            wait_continue: WaitQueue::new(),
+            inner: SpinLock::new(ProcessControlBlockInner {
+                is_zombie: false,
+                exiting: false,
+                memory_set,
+                parent,
+                child: Vec::new(),
+                exit_code: 0,
+                // 自建结构体 用于统计进程运行时数据
+                task_info_inner: TaskInfoInner::zero_init(),
+                heap_bottom,
+                program_brk,
+                fd_table,
+                cwd,
+                // 不继承等待处理的信号
+                signals: SignalFlags::empty(),
+                signal_actions,
+                stopped: false,
+                tasks: Vec::new(),
+                task_res_allocator: RecycleAllocator::new(),
+                // 同步对象不会被fork和exec继承
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                condvar_list: Vec::new(),
+                deadlock_detector: DeadlockDetector::default(),
+            }),
+        })
+    }
+
+    /// 给定elf数据, 新建进程, 主线程还没有加入就绪队列
+    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+        // user_sp是用户栈的栈顶
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let process = Self::new_empty(
+            memory_set,
+            None,
+            user_sp,
+            user_sp,
+            vec![
+                // 0 stdin
+                Some(FileDescriptor::new(Arc::new(Stdin), false)),
+                // 1 stdout
+                Some(FileDescriptor::new(Arc::new(Stdout), false)),
+                // 2 stderr
+                Some(FileDescriptor::new(Arc::new(Stdout), false)),
+            ],
+            String::from("/"),
+            SignalActions::default(),
+        );
+
+        // 创建主线程, 在User Space构建Trap Context
+        let mut process_inner = process.inner_exclusive_access();
+        let task = TaskControlBlock::new(&process, &mut process_inner, false);
+        drop(process_inner);
+        *task.inner_exclusive_access().get_trap_cx() = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.lock().token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+
+        process
+    }
+
+    /// exec系统调用, 只有当前线程还在运行时才能执行
+    pub fn exec(&self, elf_data: &[u8]) -> Result<(), Errno> {
+        let task = current_task().unwrap();
+        let mut inner = self.inner_exclusive_access();
+        if inner
+            .tasks
+            .iter()
+            .flatten()
+            .any(|other| !Arc::ptr_eq(other, &task) && !other.inner_exclusive_access().is_exited())
+        {
+            return Err(Errno::EBUSY);
+        }
+
+        // 实际上user_sp应该不会变
+        // 同时在构建的Memory Area中将数据拷贝过去
+        let (mut memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        // 当前线程成为新程序的主线程, 已经退出的线程直接回收
+        let res = TaskUserRes::new(&mut memory_set, 0, false);
+        let trap_cx_ppn = res.trap_cx_ppn(&memory_set);
+
+        // 将from_elf生成的新的地址空间替换old
+        // old的生命周期结束，回收物理页
+        inner.memory_set = memory_set;
+        inner.heap_bottom = user_sp;
+        inner.program_brk = user_sp;
+        inner.tasks = vec![Some(task.clone())];
+        inner.task_res_allocator = RecycleAllocator::new();
+        inner.task_res_allocator.alloc();
+        inner.mutex_list.clear();
+        inner.semaphore_list.clear();
+        inner.condvar_list.clear();
+        inner.deadlock_detector = DeadlockDetector::default();
+        // 原来的处理函数已经不存在
+        inner.signal_actions.reset_handlers();
+        // 关闭close-on-exec的描述符, 在释放锁以后回收
+        let closed: Vec<FileDescriptor> = inner
+            .fd_table
+            .iter_mut()
+            .filter(|fd| fd.as_ref().map_or(false, |fd| fd.cloexec))
+            .filter_map(|fd| fd.take())
+            .collect();
+
+        // 其他的都不变 只需要替换内存相关
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.res = Some(res);
+        task_inner.trap_cx_ppn = trap_cx_ppn;
+        *task_inner.get_trap_cx() = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.lock().token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        drop(task_inner);
+        drop(inner);
+        drop(closed);
+        Ok(())
+    }
+
+    /// fork系统调用, 子进程只有一个线程, 从调用fork的线程复制而来
+    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+        let task = current_task().unwrap();
+        // 获取父进程PCB
+        let mut parent_inner = self.inner_exclusive_access();
+
+        // 复制user space
+        // 采用写时复制, 此时并不会真正复制数据
+        let mut memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set);
+        // 其他线程的Trap上下文不属于子进程, 用户栈作为普通内存保留
+        for other in parent_inner.tasks.iter().flatten() {
+            if let Some(res) = other.inner_exclusive_access().res.as_ref() {
+                memory_set.remove_area_with_start_vpn(VirtAddr::from(res.trap_cx_user_va()).into());
+            }
+        }
+
+        // 复制fd_table, 包括close-on-exec标志
+        let child = Self::new_empty(
+            memory_set,
+            // 父亲引用为Self
+            Some(Arc::downgrade(self)),
+            // 与父进程完全保持一致
+            parent_inner.heap_bottom,
+            parent_inner.program_brk,
+            parent_inner.fd_table.clone(),
+            parent_inner.cwd.clone(),
+            parent_inner.signal_actions.clone(),
+        );
+
+        // 子进程的主线程从调用fork的线程复制寄存器状态
+        let mut child_inner = child.inner_exclusive_access();
+        let child_task = TaskControlBlock::new(&child, &mut child_inner, false);
+        drop(child_inner);
+        let task_inner = task.inner_exclusive_access();
+        let mut child_task_inner = child_task.inner_exclusive_access();
+        child_task_inner
+            .trap_cx_ppn
+            .get_bytes_array()
+            .copy_from_slice(task_inner.trap_cx_ppn.get_bytes_array());
+        // 继承信号屏蔽字和优先级, 继承vruntime避免通过创建进程获得更多的CPU时间
+        child_task_inner.signal_mask = task_inner.signal_mask;
+        child_task_inner.prio = task_inner.prio;
+        child_task_inner.vruntime = task_inner.vruntime;
+        // 修改kernel_stack为新分配的KernelStack
+        child_task_inner.get_trap_cx().kernel_sp = child_task.kernel_stack.get_top();
+        drop(child_task_inner);
+        drop(task_inner);
+
+        // add child
+        // 创建一个新引用
+        parent_inner.child.push(child.clone());
+        drop(parent_inner);
+        insert_into_pid2process(&child);
+
+        child
+    }
+
+    /// spwan=fork+exec
+    pub fn spwan(self: &Arc<Self>, elf_data: &[u8]) -> Arc<Self> {
+        let task = current_task().unwrap();
+        let mut parent_inner = self.inner_exclusive_access();
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        // copy fd table, 与exec相同, 不继承close-on-exec的描述符
+        let new_fd_table: Vec<Option<FileDescriptor>> = parent_inner
+            .fd_table
+            .iter()
+            .map(|fd| fd.clone().filter(|fd| !fd.cloexec))
+            .collect();
+
+        // 与exec相同, 用户的处理函数恢复为默认处理
+        let mut signal_actions = parent_inner.signal_actions.clone();
+        signal_actions.reset_handlers();
+
+        let child = Self::new_empty(
+            memory_set,
+            Some(Arc::downgrade(self)),
+            user_sp,
+            user_sp,
+            new_fd_table,
+            parent_inner.cwd.clone(),
+            signal_actions,
+        );
+        let mut child_inner = child.inner_exclusive_access();
+        let child_task = TaskControlBlock::new(&child, &mut child_inner, false);
+        drop(child_inner);
+        let mut child_task_inner = child_task.inner_exclusive_access();
+        child_task_inner.signal_mask = task.inner_exclusive_access().signal_mask;
+        *child_task_inner.get_trap_cx() = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.lock().token(),
+            child_task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        drop(child_task_inner);
+        parent_inner.child.push(child.clone());
+        drop(parent_inner);
+        insert_into_pid2process(&child);
+        child
+    }
+
+    /// change the location of the program break. return None if failed
+    pub fn change_program_brk(&self, size: i32) -> Option<usize> {
+        let mut inner = self.inner_exclusive_access();
+        let heap_bottom = inner.heap_bottom;
+        let old_break = inner.program_brk;
+        let new_brk = inner.program_brk as isize + size as isize;
+
+        // 如果新的位置小于heap底部
+        if new_brk < heap_bottom as isize {
+            return None;
+        }
+
+        let result = if size < 0 {
+            // 回收
+            inner
+                .memory_set
+                .shrink_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
+        } else {
+            // 增加
+            inner
+                .memory_set
+                .append_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
+        };
+
+        if result {
+            inner.program_brk = new_brk as usize;
+            Some(old_break)
+        } else {
+            None
+        }
+    }
+}
+
+impl ProcessControlBlockInner {
+    /// get token
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+
+    /// 分配一个tid
+    pub fn alloc_tid(&mut self) -> usize {
+        self.task_res_allocator.alloc()
+    }
+
+    /// 回收已经退出的线程, 之后tid可以复用
+    pub fn remove_task(&mut self, tid: usize) -> Option<Arc<TaskControlBlock>> {
+        let task = self.tasks.get_mut(tid)?.take()?;
+        self.task_res_allocator.dealloc(tid);
+        self.deadlock_detector.remove_thread(tid);
+        Some(task)
+    }
+
+    /// 标记没有屏蔽signal的线程被打断, 返回其中处于可中断等待的线程
+    /// 调用者在释放进程的锁以后唤醒它们
+    pub fn interrupt_tasks(&self, signal: SignalFlags) -> Vec<Arc<TaskControlBlock>> {
+        self.tasks
+            .iter()
+            .flatten()
+            .filter(|task| {
+                let mut inner = task.inner_exclusive_access();
+                if (signal - inner.signal_mask).is_empty() {
+                    return false;
+                }
+                inner.interrupted = true;
+                inner.task_status == TaskStatus::Blocked && inner.interruptible
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// 通过tid查找线程
+    pub fn get_task(&self, tid: usize) -> Option<Arc<TaskControlBlock>> {
+        self.tasks.get(tid).cloned().flatten()
+    }
+
+    /// 还没有退出的线程数量
+    pub fn thread_count(&self) -> usize {
+        self.tasks
+            .iter()
+            .flatten()
+            .filter(|task| !task.inner_exclusive_access().is_exited())
+            .count()
+    }
+
+    /// 更新当前系统调用计数器
+    pub fn update_syscall_times(&mut self, syscall_id: usize) {
+        // 不支持的系统调用编号可能超出范围
+        if let Some(times) = self.task_info_inner.syscall_times.get_mut(syscall_id) {
+            *times += 1;
+        }
+    }
+
+    /// 映射地址空间, 与已有区域重叠时返回false
+    /// 只保留地址范围, 物理页面在第一次访问时分配
+    pub fn mapping_address_space(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+        shared: bool,
+        file: Option<MmapFile>,
+    ) -> bool {
+        if self.memory_set.is_overlapping(start_va, end_va) {
+            return false;
+        }
+
+        self.memory_set
+            .insert_mmap_area(start_va, end_va, map_perm, shared, file);
+        true
+    }
+
+    /// 取消一块地址空间的映射
+    pub fn unmapping_address_space(&mut self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
+        self.memory_set.munmap_area(start_va, end_va)
+    }
+
+    /// 分配编号最小的空闲fd, 已经有MAX_FD_NUM个时返回EMFILE
+    /// 返回的fd需要立即填入, 否则下一次还会分配到它
+    pub fn alloc_fd(&mut self) -> Result<usize, Errno> {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            Ok(fd)
+        } else if self.fd_table.len() < MAX_FD_NUM {
+            // 如果前面都有 那么在尾部增加一个
+            self.fd_table.push(None);
+            Ok(self.fd_table.len() - 1)
+        } else {
+            Err(Errno::EMFILE)
+        }
+    }
+}
@@ -1,12 +1,18 @@
 use crate::{
-    mm::{MapPermission, VirtAddr},
-    sync::UPSafeCell,
+    config::MAX_HART_NUM,
+    fs::poll_stdin,
+    mm::VirtAddr,
+    sync::SpinLock,
+    timer::{check_timer, get_time_ms},
     trap::TrapContext,
 };
 
 use super::{
-    manager::fetch_task, switch::__switch, task::TaskInfoInner, TaskContext, TaskControlBlock,
-    TaskStatus,
+    manager::{add_task, fetch_task},
+    process::ProcessControlBlock,
+    switch::__switch,
+    task::TaskInfoInner,
+    TaskContext, TaskControlBlock, TaskStatus,
 };
 use alloc::sync::Arc;
 use lazy_static::*;
@@ -27,7 +33,23 @@ impl Processor {
 }
 
 lazy_static! {
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+    /// 每个核心一个Processor, 通过hartid索引
+    pub static ref PROCESSORS: [SpinLock<Processor>; MAX_HART_NUM] =
+        core::array::from_fn(|_| SpinLock::new(Processor::new()));
+}
+
+/// 当前核心的hartid, 启动时保存在tp寄存器中
+pub fn hart_id() -> usize {
+    let hart_id;
+    unsafe {
+        core::arch::asm!("mv {}, tp", out(reg) hart_id);
+    }
+    hart_id
+}
+
+/// 当前核心的Processor
+fn current_processor() -> &'static SpinLock<Processor> {
+    &PROCESSORS[hart_id()]
 }
 
 impl Processor {
@@ -46,18 +68,23 @@ impl Processor {
 
 /// 包装函数
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    current_processor().lock().take_current()
 }
 
 /// 包装函数
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    current_processor().lock().current()
+}
+
+/// 当前线程所属的进程
+pub fn current_process() -> Arc<ProcessControlBlock> {
+    current_task().unwrap().process.upgrade().unwrap()
 }
 
 /// 包装函数
 pub fn current_user_token() -> usize {
-    let task = current_task().unwrap();
-    let token = task.inner_exclusive_access().get_user_token();
+    let process = current_process();
+    let token = process.inner_exclusive_access().get_user_token();
     token
 }
 
@@ -69,15 +96,26 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
         .get_trap_cx()
 }
 
+/// 当前线程的Trap上下文在用户地址空间中的地址
+pub fn current_trap_cx_user_va() -> usize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .trap_cx_user_va()
+}
+
 /// 包装函数
 pub fn current_task_info_inner() -> TaskInfoInner {
-    current_task().unwrap().get_task_info_inner()
+    current_process().inner_exclusive_access().task_info_inner
 }
 
 /// 当前核心运行任务, 从idle控制流转移到某个任务开始执行
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        let mut processor = current_processor().lock();
         // 获取下一个任务
         if let Some(task) = fetch_task() {
             // 取一个任务
@@ -86,9 +124,17 @@ pub fn run_tasks() {
             let mut task_inner = task.inner_exclusive_access();
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
             task_inner.task_status = TaskStatus::Running;
-
             // 手动归还
             drop(task_inner);
+
+            // 进程中的任意一个线程第一次运行
+            if let Some(process) = task.process.upgrade() {
+                let mut process_inner = process.inner_exclusive_access();
+                if process_inner.task_info_inner.first_run_flag {
+                    process_inner.task_info_inner.first_run_flag = false;
+                    process_inner.task_info_inner.first_run_time = get_time_ms();
+                }
+            }
             // 由于是Arc引用，只会被引用一次
             // task从任务管理器转移到process中
             processor.current = Some(task);
@@ -99,15 +145,50 @@ pub fn run_tasks() {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
 
-            // idle任务返回处，继续找下一个任务调度
+            // idle任务返回处, 此时任务的上下文已经保存
+            // 之后才能放回就绪队列, 否则其他核心可能在保存完成之前调度该任务
+            if let Some(task) = take_current_task() {
+                let mut task_inner = task.inner_exclusive_access();
+                let mut ready = false;
+                match task_inner.task_status {
+                    TaskStatus::Ready => ready = true,
+                    // 之后由wakeup_task放回就绪队列
+                    TaskStatus::Blocked => task_inner.parked = true,
+                    // 退出的线程在这里释放最后一个引用, 之后才能回收内核栈
+                    _ => {}
+                }
+                drop(task_inner);
+
+                if ready {
+                    add_task(task);
+                }
+            }
             // loop
+        } else {
+            drop(processor);
+            idle_poll();
         }
     }
 }
 
+/// 没有任务可以运行时轮询的事件
+/// idle控制流中不会响应时钟中断, 这些事件需要在这里检查
+fn idle_poll() {
+    check_timer();
+    poll_stdin();
+}
+
 /// 切换回idle线程
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+    // 切换以后其他任务无法获取这些锁
+    #[cfg(feature = "lock-debug")]
+    assert_eq!(
+        crate::sync::intr_depth(),
+        0,
+        "schedule while holding spin locks"
+    );
+
+    let mut processor = current_processor().lock();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
 
     drop(processor);
@@ -124,29 +205,25 @@ impl Processor {
     }
 }
 
-/// 更新当前任务的信息
+/// 更新当前进程的信息
 pub fn update_current_task_syscall_times(syscall_id: usize) {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
     inner.update_syscall_times(syscall_id);
     drop(inner);
-    drop(task);
+    drop(process);
 }
 
-/// 给当前任务映射一块内存
-pub fn mapping_address_space_for_current_task(
-    start_va: VirtAddr,
-    end_va: VirtAddr,
-    map_perm: MapPermission,
-) {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
-    inner.mapping_address_space(start_va, end_va, map_perm);
+/// 处理当前进程的缺页异常: 按需分配或者写时复制
+pub fn page_fault_for_current_task(va: VirtAddr, is_store: bool) -> bool {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    inner.memory_set.page_fault(va.floor(), is_store)
 }
 
-/// 给当前任务取消映射一块内存
-pub fn unmapping_address_space_for_current_task(start_va: VirtAddr, end_va: VirtAddr) {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
-    inner.unmapping_address_space(start_va, end_va);
+/// 给当前进程取消映射一块内存, 没有区域包含该范围时返回false
+pub fn unmapping_address_space_for_current_task(start_va: VirtAddr, end_va: VirtAddr) -> bool {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    inner.unmapping_address_space(start_va, end_va)
 }
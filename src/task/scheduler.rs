@@ -1,2 +1,284 @@
-/// STRIDE_MAX
-pub const BIG_STRIDE: usize = 250;
+//! Scheduler: 就绪队列的调度算法
+//! 默认的算法由feature选择, 运行时可以通过sys_set_scheduler切换
+
+use super::TaskControlBlock;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BinaryHeap, VecDeque};
+use alloc::sync::Arc;
+use core::cmp::{Ordering, Reverse};
+
+/// STRIDE_MAX, 远大于MAX_PRIO, 所有优先级的步长都不为0
+pub const BIG_STRIDE: usize = 1 << 32;
+/// 优先级的上限, sys_set_prio设置更大的值时取该值
+pub const MAX_PRIO: usize = 1 << 16;
+
+#[cfg(any(
+    all(feature = "sched-rr", feature = "sched-mlfq"),
+    all(feature = "sched-rr", feature = "sched-cfs"),
+    all(feature = "sched-mlfq", feature = "sched-cfs"),
+))]
+compile_error!("sched-rr, sched-mlfq和sched-cfs最多只能启用一个");
+
+/// MLFQ的队列数, 第level层的时间片为2^level个时钟中断
+const MLFQ_LEVELS: usize = 4;
+/// MLFQ每隔这么多个时钟中断将所有任务放回最高优先级, 避免饥饿
+const MLFQ_BOOST_TICKS: usize = 100;
+
+/// 调度算法
+/// 调用时持有任务管理器的锁, 可以获取任务的锁
+pub trait Scheduler: Send {
+    /// 加入一个就绪的任务
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// 取出下一个运行的任务
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// 正在运行的任务经过一个时钟中断, 返回是否需要切换
+    fn tick(&mut self, task: &Arc<TaskControlBlock>) -> bool;
+}
+
+/// 可以选择的调度算法
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SchedPolicy {
+    /// 每次调度stride最小的任务, 优先级越高stride增长越慢
+    Stride,
+    /// 按照就绪顺序轮流执行, 时间片为一个时钟中断
+    RoundRobin,
+    /// 多级反馈队列, 用完时间片的任务降级
+    Mlfq,
+    /// 与Linux CFS类似, 每次调度按优先级加权的运行时间最少的任务
+    Cfs,
+}
+
+impl SchedPolicy {
+    /// 启用的feature对应的调度算法, 都没有启用时为Stride
+    pub fn default_policy() -> Self {
+        if cfg!(feature = "sched-rr") {
+            Self::RoundRobin
+        } else if cfg!(feature = "sched-mlfq") {
+            Self::Mlfq
+        } else if cfg!(feature = "sched-cfs") {
+            Self::Cfs
+        } else {
+            Self::Stride
+        }
+    }
+
+    /// sys_set_scheduler使用的编号
+    pub fn from_id(id: usize) -> Option<Self> {
+        match id {
+            0 => Some(Self::Stride),
+            1 => Some(Self::RoundRobin),
+            2 => Some(Self::Mlfq),
+            3 => Some(Self::Cfs),
+            _ => None,
+        }
+    }
+
+    /// 创建一个空的调度器
+    pub fn new_scheduler(self) -> Box<dyn Scheduler> {
+        match self {
+            Self::Stride => Box::new(StrideScheduler::new()),
+            Self::RoundRobin => Box::new(RoundRobinScheduler::new()),
+            Self::Mlfq => Box::new(MlfqScheduler::new()),
+            Self::Cfs => Box::new(CfsScheduler::new()),
+        }
+    }
+}
+
+/// 按(key, seq)排序的任务, seq使key相同的任务按加入顺序调度
+struct KeyedTask {
+    key: usize,
+    seq: usize,
+    task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for KeyedTask {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for KeyedTask {}
+
+impl PartialOrd for KeyedTask {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for KeyedTask {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.key, self.seq).cmp(&(other.key, other.seq))
+    }
+}
+
+/// Stride调度, 用小根堆找到stride最小的任务
+struct StrideScheduler {
+    heap: BinaryHeap<Reverse<KeyedTask>>,
+    next_seq: usize,
+}
+
+impl StrideScheduler {
+    fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            next_seq: 0,
+        }
+    }
+}
+
+impl Scheduler for StrideScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let key = task.inner_exclusive_access().stride;
+        self.heap.push(Reverse(KeyedTask {
+            key,
+            seq: self.next_seq,
+            task,
+        }));
+        self.next_seq += 1;
+    }
+
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let task = self.heap.pop()?.0.task;
+        task.update_stride();
+        Some(task)
+    }
+
+    fn tick(&mut self, _task: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+}
+
+/// 时间片轮转
+struct RoundRobinScheduler {
+    queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl RoundRobinScheduler {
+    fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.queue.push_back(task);
+    }
+
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.queue.pop_front()
+    }
+
+    fn tick(&mut self, _task: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+}
+
+/// 多级反馈队列
+/// 任务在一层中累计用完时间片以后降级, 主动让出CPU不会重置已经使用的时间
+struct MlfqScheduler {
+    queues: [VecDeque<Arc<TaskControlBlock>>; MLFQ_LEVELS],
+    /// 距离上一次提升经过的时钟中断
+    ticks: usize,
+}
+
+impl MlfqScheduler {
+    fn new() -> Self {
+        Self {
+            queues: Default::default(),
+            ticks: 0,
+        }
+    }
+
+    /// 将所有就绪的任务放回最高优先级
+    fn boost(&mut self) {
+        for level in 1..MLFQ_LEVELS {
+            while let Some(task) = self.queues[level].pop_front() {
+                let mut inner = task.inner_exclusive_access();
+                inner.level = 0;
+                inner.slice_ticks = 0;
+                drop(inner);
+                self.queues[0].push_back(task);
+            }
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let level = task.inner_exclusive_access().level.min(MLFQ_LEVELS - 1);
+        self.queues[level].push_back(task);
+    }
+
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+
+    fn tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        self.ticks += 1;
+        if self.ticks >= MLFQ_BOOST_TICKS {
+            self.ticks = 0;
+            self.boost();
+        }
+
+        let mut inner = task.inner_exclusive_access();
+        inner.slice_ticks += 1;
+        if inner.slice_ticks >= 1 << inner.level {
+            inner.slice_ticks = 0;
+            inner.level = (inner.level + 1).min(MLFQ_LEVELS - 1);
+            return true;
+        }
+        // 有更高优先级的任务就绪时立即切换
+        let level = inner.level;
+        drop(inner);
+        self.queues[..level].iter().any(|queue| !queue.is_empty())
+    }
+}
+
+/// 与CFS类似, 红黑树用BTreeMap代替
+/// vruntime每个时钟中断增加BIG_STRIDE / prio, 只有实际运行的时间会被计入
+struct CfsScheduler {
+    tree: BTreeMap<(usize, usize), Arc<TaskControlBlock>>,
+    next_seq: usize,
+    /// 单调增加, 阻塞很久的任务唤醒以后从这里开始, 不会长时间独占CPU
+    min_vruntime: usize,
+}
+
+impl CfsScheduler {
+    fn new() -> Self {
+        Self {
+            tree: BTreeMap::new(),
+            next_seq: 0,
+            min_vruntime: 0,
+        }
+    }
+}
+
+impl Scheduler for CfsScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut inner = task.inner_exclusive_access();
+        inner.vruntime = inner.vruntime.max(self.min_vruntime);
+        let key = (inner.vruntime, self.next_seq);
+        drop(inner);
+        self.tree.insert(key, task);
+        self.next_seq += 1;
+    }
+
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let ((vruntime, _), task) = self.tree.pop_first()?;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+        Some(task)
+    }
+
+    fn tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut inner = task.inner_exclusive_access();
+        inner.vruntime += BIG_STRIDE / inner.prio;
+        let vruntime = inner.vruntime;
+        drop(inner);
+        // 只在有运行时间更少的任务时切换
+        self.tree
+            .first_key_value()
+            .map_or(false, |(&(min, _), _)| min < vruntime)
+    }
+}
@@ -0,0 +1,378 @@
+//! Signal: 信号的定义, 以及返回用户态之前的信号投递
+
+use super::process::ProcessControlBlock;
+use super::task::{exit_group_and_run_next, TaskControlBlockInner};
+use super::{current_task, wakeup_task, TaskControlBlock};
+use crate::mm::MemorySet;
+use alloc::vec::Vec;
+
+/// 最大的信号编号
+pub const MAX_SIG: usize = 31;
+/// 默认处理
+pub const SIG_DFL: usize = 0;
+/// 忽略信号
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// 信号集合, 第signum - 1位对应信号signum
+    pub struct SignalFlags: u64 {
+        /// 终端挂断
+        const SIGHUP = 1 << 0;
+        /// 键盘中断
+        const SIGINT = 1 << 1;
+        /// 键盘退出
+        const SIGQUIT = 1 << 2;
+        /// 非法指令
+        const SIGILL = 1 << 3;
+        /// 断点
+        const SIGTRAP = 1 << 4;
+        /// abort
+        const SIGABRT = 1 << 5;
+        /// 总线错误
+        const SIGBUS = 1 << 6;
+        /// 算术异常
+        const SIGFPE = 1 << 7;
+        /// 强制终止, 不能被捕获或屏蔽
+        const SIGKILL = 1 << 8;
+        /// 用户自定义信号1
+        const SIGUSR1 = 1 << 9;
+        /// 非法内存访问
+        const SIGSEGV = 1 << 10;
+        /// 用户自定义信号2
+        const SIGUSR2 = 1 << 11;
+        /// 写入没有读者的管道
+        const SIGPIPE = 1 << 12;
+        /// 定时器到期
+        const SIGALRM = 1 << 13;
+        /// 终止
+        const SIGTERM = 1 << 14;
+        /// 协处理器栈错误
+        const SIGSTKFLT = 1 << 15;
+        /// 子进程退出
+        const SIGCHLD = 1 << 16;
+        /// 继续执行被暂停的进程
+        const SIGCONT = 1 << 17;
+        /// 暂停, 不能被捕获或屏蔽
+        const SIGSTOP = 1 << 18;
+        /// 终端暂停
+        const SIGTSTP = 1 << 19;
+        /// 后台进程读终端
+        const SIGTTIN = 1 << 20;
+        /// 后台进程写终端
+        const SIGTTOU = 1 << 21;
+        /// socket紧急数据
+        const SIGURG = 1 << 22;
+        /// 超过CPU时间限制
+        const SIGXCPU = 1 << 23;
+        /// 超过文件大小限制
+        const SIGXFSZ = 1 << 24;
+        /// 虚拟定时器到期
+        const SIGVTALRM = 1 << 25;
+        /// profiling定时器到期
+        const SIGPROF = 1 << 26;
+        /// 终端窗口大小改变
+        const SIGWINCH = 1 << 27;
+        /// 可以进行I/O
+        const SIGIO = 1 << 28;
+        /// 电源故障
+        const SIGPWR = 1 << 29;
+        /// 错误的系统调用
+        const SIGSYS = 1 << 30;
+    }
+}
+
+impl SignalFlags {
+    /// 只包含signum的集合, signum不合法时返回None
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if (1..=MAX_SIG).contains(&signum) {
+            Some(Self::from_bits_truncate(1 << (signum - 1)))
+        } else {
+            None
+        }
+    }
+
+    /// 集合中编号最小的信号
+    fn first_signum(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.bits().trailing_zeros() as usize + 1)
+        }
+    }
+
+    /// 不能被屏蔽, 也不能修改处理方式的信号
+    pub fn unblockable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+
+    /// 默认处理方式为暂停的信号
+    fn stop_signals() -> Self {
+        Self::SIGSTOP | Self::SIGTSTP | Self::SIGTTIN | Self::SIGTTOU
+    }
+
+    /// 默认处理方式为忽略的信号
+    fn ignored_by_default() -> Self {
+        Self::SIGCHLD | Self::SIGCONT | Self::SIGURG | Self::SIGWINCH
+    }
+}
+
+/// 信号的处理方式, 与用户态的结构保持一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalAction {
+    /// 处理函数的地址, 或者SIG_DFL/SIG_IGN
+    pub handler: usize,
+    /// 执行处理函数期间额外屏蔽的信号
+    pub mask: SignalFlags,
+    /// 处理函数返回的地址, 一般是调用sigreturn的函数
+    /// 为0时处理函数需要自己调用sigreturn
+    pub restorer: usize,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+            restorer: 0,
+        }
+    }
+}
+
+/// 每个信号的处理方式, 通过signum索引
+#[derive(Clone)]
+pub struct SignalActions {
+    /// 第0项不使用
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
+    }
+}
+
+impl SignalActions {
+    /// exec以后用户的处理函数不再存在, 恢复为默认处理, 被忽略的信号保持忽略
+    pub fn reset_handlers(&mut self) {
+        for action in self.table.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+    }
+}
+
+/// 投递信号时保存在用户栈上的上下文, sigreturn时恢复
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalFrame {
+    /// 被打断时的通用寄存器
+    pub x: [usize; 32],
+    /// 被打断时的sepc
+    pub sepc: usize,
+    /// 被打断时的信号屏蔽字
+    pub mask: SignalFlags,
+}
+
+impl ProcessControlBlock {
+    /// 向进程发送信号, 由没有屏蔽该信号的线程处理
+    pub fn send_signal(&self, signal: SignalFlags) {
+        let mut inner = self.inner_exclusive_access();
+        // SIGCONT和暂停信号互相取消
+        if signal.contains(SignalFlags::SIGCONT) {
+            inner.signals.remove(SignalFlags::stop_signals());
+        }
+        if signal.intersects(SignalFlags::stop_signals()) {
+            inner.signals.remove(SignalFlags::SIGCONT);
+        }
+        inner.signals.insert(signal);
+
+        // 没有屏蔽该信号的线程放弃可中断的等待, 返回用户态处理信号
+        let signum = signal.first_signum().unwrap();
+        let interrupted = if interrupts(signal, &inner.signal_actions.table[signum]) {
+            inner.interrupt_tasks(signal)
+        } else {
+            Vec::new()
+        };
+
+        // 被暂停的进程只有SIGCONT和SIGKILL可以唤醒
+        let wake = inner.stopped && signal.intersects(SignalFlags::SIGCONT | SignalFlags::SIGKILL);
+        if wake {
+            inner.stopped = false;
+        }
+        drop(inner);
+
+        for task in interrupted {
+            wakeup_task(task);
+        }
+        if wake {
+            self.wait_continue.wake_all();
+        }
+    }
+}
+
+impl TaskControlBlock {
+    /// 因为当前线程的异常向进程发送信号
+    /// 该信号被屏蔽或者忽略时无法继续执行, 恢复为默认处理
+    pub fn force_signal(&self, signal: SignalFlags) {
+        let signum = signal.first_signum().unwrap();
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        let mut inner = self.inner_exclusive_access();
+        if inner.signal_mask.contains(signal)
+            || process_inner.signal_actions.table[signum].handler == SIG_IGN
+        {
+            inner.signal_mask.remove(signal);
+            process_inner.signal_actions.table[signum] = SignalAction::default();
+        }
+        drop(inner);
+        drop(process_inner);
+        process.send_signal(signal);
+    }
+}
+
+/// 信号的默认处理方式
+enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+}
+
+fn default_action(signal: SignalFlags) -> DefaultAction {
+    if signal.intersects(SignalFlags::stop_signals()) {
+        DefaultAction::Stop
+    } else if signal.intersects(SignalFlags::ignored_by_default()) {
+        DefaultAction::Ignore
+    } else {
+        DefaultAction::Terminate
+    }
+}
+
+/// 信号是否打断可中断的等待
+/// 被忽略的信号不打断, 暂停信号也不打断, 等待中的线程返回用户态之前才会暂停
+fn interrupts(signal: SignalFlags, action: &SignalAction) -> bool {
+    match action.handler {
+        SIG_IGN => false,
+        SIG_DFL => matches!(default_action(signal), DefaultAction::Terminate),
+        _ => true,
+    }
+}
+
+/// 返回用户态之前处理当前线程的信号
+/// 默认处理为终止时不会返回, 用户处理函数在返回用户态以后执行
+pub fn handle_signals() {
+    loop {
+        let task = current_task().unwrap();
+        let process = task.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        // 其他线程已经结束了进程
+        if process_inner.exiting {
+            drop(process_inner);
+            drop(process);
+            drop(task);
+            exit_group_and_run_next(0);
+            return;
+        }
+        // 进程被暂停, 所有线程在返回用户态之前等待SIGCONT
+        if process_inner.stopped {
+            drop(process_inner);
+            drop(task);
+            process
+                .wait_continue
+                .wait_until(|| !process.inner_exclusive_access().stopped);
+            continue;
+        }
+
+        let mut inner = task.inner_exclusive_access();
+        let signum = match (process_inner.signals - inner.signal_mask).first_signum() {
+            Some(signum) => signum,
+            None => {
+                inner.interrupted = false;
+                return;
+            }
+        };
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        let action = process_inner.signal_actions.table[signum];
+
+        if action.handler == SIG_IGN {
+            process_inner.signals.remove(signal);
+            continue;
+        }
+        if action.handler != SIG_DFL {
+            process_inner.signals.remove(signal);
+            let delivered =
+                setup_signal_frame(&mut process_inner.memory_set, &mut inner, signum, &action);
+            // 其余的信号在下一次返回用户态时处理, 在此之前处理函数中的等待仍然会被打断
+            inner.interrupted = !(process_inner.signals - inner.signal_mask).is_empty();
+            drop(inner);
+            drop(process_inner);
+            drop(process);
+            drop(task);
+            if !delivered {
+                // 用户栈无法写入, 只能终止
+                let signum = SignalFlags::SIGSEGV.first_signum().unwrap();
+                exit_group_and_run_next(-(signum as i32));
+            }
+            return;
+        }
+
+        match default_action(signal) {
+            DefaultAction::Ignore => process_inner.signals.remove(signal),
+            DefaultAction::Terminate => {
+                drop(inner);
+                drop(process_inner);
+                drop(process);
+                drop(task);
+                exit_group_and_run_next(-(signum as i32));
+            }
+            DefaultAction::Stop => {
+                // 在持有锁时标记, send_signal之后才能看到stopped, 唤醒不会丢失
+                process_inner.signals.remove(signal);
+                process_inner.stopped = true;
+            }
+        }
+    }
+}
+
+/// 在用户栈上保存当前上下文, 然后修改trap上下文跳转到处理函数
+/// 用户栈无法写入时返回false
+fn setup_signal_frame(
+    memory_set: &mut MemorySet,
+    inner: &mut TaskControlBlockInner,
+    signum: usize,
+    action: &SignalAction,
+) -> bool {
+    let trap_cx = inner.get_trap_cx();
+    let frame = SignalFrame {
+        x: trap_cx.x,
+        sepc: trap_cx.sepc,
+        mask: inner.signal_mask,
+    };
+    let frame_size = core::mem::size_of::<SignalFrame>();
+    let frame_ptr = match trap_cx.x[2].checked_sub(frame_size) {
+        Some(sp) => sp & !0xf,
+        None => return false,
+    };
+    let frame_bytes = unsafe {
+        core::slice::from_raw_parts(&frame as *const SignalFrame as *const u8, frame_size)
+    };
+    if !memory_set.write_user(frame_ptr, frame_bytes) {
+        return false;
+    }
+
+    // 处理函数执行期间屏蔽该信号本身以及action.mask
+    let signal = SignalFlags::from_signum(signum).unwrap();
+    inner.signal_mask |= (action.mask | signal) - SignalFlags::unblockable();
+
+    // handler(signum), 返回到restorer
+    trap_cx.x[2] = frame_ptr;
+    trap_cx.x[10] = signum;
+    if action.restorer != 0 {
+        trap_cx.x[1] = action.restorer;
+    }
+    trap_cx.sepc = action.handler;
+    true
+}
@@ -1,62 +1,139 @@
 //! Type related to task manager
-
-use core::cell::RefMut;
+//! TaskControlBlock是一个线程, 地址空间等资源由所属的ProcessControlBlock持有
 
 use alloc::sync::{Arc, Weak};
-use alloc::vec;
 use alloc::vec::Vec;
 
-use super::pid::{pid_alloc, KernelStack, PidHandle};
-use super::{schedule, take_current_task, TaskContext, BIG_STRIDE, INITPROC};
-use crate::config::{MAX_SYSCALL_NUM, TRAP_CONTEXT_BASE};
-use crate::fs::{File, Stdin, Stdout};
-use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
-use crate::trap::{trap_handler, TrapContext};
+use super::pid::{kstack_alloc, KernelStack};
+use super::process::{ProcessControlBlock, ProcessControlBlockInner};
+use super::signal::SignalFlags;
+use super::{current_task, schedule, wakeup_task, TaskContext, BIG_STRIDE, INITPROC};
+use crate::config::{MAX_SYSCALL_NUM, PAGE_SIZE, TRAP_CONTEXT_BASE, USER_STACK_SIZE};
+use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr};
+use crate::sync::{SpinLock, SpinLockGuard};
+use crate::trap::TrapContext;
 
 /// struct of TCB
 pub struct TaskControlBlock {
     // immutable
-    /// Pid
-    pub pid: PidHandle,
+    /// 所属进程
+    pub process: Weak<ProcessControlBlock>,
     /// 内核栈
     pub kernel_stack: KernelStack,
     // mutable
     /// 可变信息
-    inner: UPSafeCell<TaskControlBlockInner>,
+    inner: SpinLock<TaskControlBlockInner>,
 }
 
 /// struct of TCB inner
 pub struct TaskControlBlockInner {
+    /// 线程在用户地址空间中的资源, 退出时释放
+    pub res: Option<TaskUserRes>,
     /// 上下文所在的ppn
     pub trap_cx_ppn: PhysPageNum,
-    /// base size
-    pub base_size: usize,
     /// 任务上下文
     pub task_cx: TaskContext,
     /// 任务状态
     pub task_status: TaskStatus,
-    /// address space
-    pub memory_set: MemorySet,
-    /// 父进程
-    // Weak不会影响父进程的引用计数
-    pub parent: Option<Weak<TaskControlBlock>>,
-    /// 子进程
-    pub child: Vec<Arc<TaskControlBlock>>,
-    /// 进程退出code
-    pub exit_code: i32,
-    /// task info inner
-    pub task_info_inner: TaskInfoInner,
-    /// heap bottom
-    pub heap_bottom: usize,
-    /// program break
-    pub program_brk: usize,
+    /// 已经完成切换: 阻塞的任务唤醒时需要放回就绪队列
+    pub parked: bool,
+    /// 线程退出code, 还没有退出时为None
+    pub exit_code: Option<i32>,
     /// 优先级
     pub prio: usize,
     /// Stride优先级
     pub stride: usize,
-    /// 打开文件表
-    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// CFS中按优先级加权的运行时间
+    pub vruntime: usize,
+    /// MLFQ中所在的队列
+    pub level: usize,
+    /// MLFQ中在当前队列已经使用的时间片
+    pub slice_ticks: usize,
+    /// 被屏蔽的信号
+    pub signal_mask: SignalFlags,
+    /// 收到了需要处理的信号或者进程正在退出, 可中断的等待应当放弃并返回EINTR
+    /// 返回用户态处理完信号以后清除
+    pub interrupted: bool,
+    /// 当前的阻塞可以被信号打断
+    pub interruptible: bool,
+}
+
+/// 线程在用户地址空间中的资源: 编号, Trap上下文以及用户栈
+pub struct TaskUserRes {
+    /// 线程在进程内的编号
+    pub tid: usize,
+    /// 用户栈的底部, 主线程使用加载elf时分配的栈, 此时为None
+    pub ustack_bottom: Option<usize>,
+}
+
+/// tid对应的Trap上下文所在页面, 从TRAP_CONTEXT_BASE向下排列
+fn trap_cx_bottom_from_tid(tid: usize) -> usize {
+    TRAP_CONTEXT_BASE - tid * PAGE_SIZE
+}
+
+impl TaskUserRes {
+    /// 在地址空间中映射tid的Trap上下文, alloc_ustack时另外分配一个用户栈
+    pub fn new(memory_set: &mut MemorySet, tid: usize, alloc_ustack: bool) -> Self {
+        let trap_cx_bottom = trap_cx_bottom_from_tid(tid);
+        memory_set.insert_framed_area(
+            trap_cx_bottom.into(),
+            (trap_cx_bottom + PAGE_SIZE).into(),
+            MapPermission::R | MapPermission::W,
+        );
+
+        // 主线程的栈后面是堆, 其他线程的栈与mmap一样查找空闲区域
+        // 栈底下方保留一个不能访问的保护页面, 栈溢出时触发缺页异常, 而不是改写相邻的区域
+        let ustack_bottom = if alloc_ustack {
+            let guard = memory_set.find_free_area(PAGE_SIZE + USER_STACK_SIZE).0;
+            let ustack_bottom = guard + PAGE_SIZE;
+            memory_set.insert_mmap_area(
+                guard.into(),
+                ustack_bottom.into(),
+                MapPermission::U,
+                false,
+                None,
+            );
+            memory_set.insert_framed_area(
+                ustack_bottom.into(),
+                (ustack_bottom + USER_STACK_SIZE).into(),
+                MapPermission::R | MapPermission::W | MapPermission::U,
+            );
+            Some(ustack_bottom)
+        } else {
+            None
+        };
+
+        Self { tid, ustack_bottom }
+    }
+
+    /// Trap上下文在用户地址空间中的地址
+    pub fn trap_cx_user_va(&self) -> usize {
+        trap_cx_bottom_from_tid(self.tid)
+    }
+
+    /// Trap上下文所在的物理页面
+    pub fn trap_cx_ppn(&self, memory_set: &MemorySet) -> PhysPageNum {
+        memory_set
+            .translate(VirtAddr::from(self.trap_cx_user_va()).into())
+            .unwrap()
+            .ppn()
+    }
+
+    /// 用户栈的栈顶
+    pub fn ustack_top(&self) -> Option<usize> {
+        self.ustack_bottom
+            .map(|ustack_bottom| ustack_bottom + USER_STACK_SIZE)
+    }
+
+    /// 取消Trap上下文和用户栈的映射, 包括用户栈下方的保护页面
+    /// tid在线程被回收以后才能复用, 因此不在这里释放
+    pub fn dealloc(self, memory_set: &mut MemorySet) {
+        memory_set.remove_area_with_start_vpn(VirtAddr::from(self.trap_cx_user_va()).into());
+        if let Some(ustack_bottom) = self.ustack_bottom {
+            memory_set.remove_area_with_start_vpn(VirtAddr::from(ustack_bottom).into());
+            memory_set.remove_area_with_start_vpn(VirtAddr::from(ustack_bottom - PAGE_SIZE).into());
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -90,217 +167,63 @@ pub enum TaskStatus {
     Ready,
     /// Running
     Running,
+    /// 等待某个事件, 被唤醒以后才能继续执行
+    Blocked,
     /// Exited
     Zombie,
 }
 
 impl TaskControlBlock {
     /// 获取可变引用
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
-        self.inner.exclusive_access()
+    pub fn inner_exclusive_access(&self) -> SpinLockGuard<'_, TaskControlBlockInner> {
+        self.inner.lock()
     }
 
-    /// 获取 用户页表
-    pub fn get_user_token(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        inner.memory_set.token()
-    }
-
-    /// 获取PID
-    pub fn get_pid(&self) -> usize {
-        self.pid.0
-    }
-
-    /// 给定elf数据, 新建进程
-    pub fn new(elf_data: &[u8]) -> Self {
-        // user_sp是用户栈的栈顶
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-
-        // 获得TRAP上下文的ppn
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-
-        // 分配一个PID
-        let pid_handle = pid_alloc();
-        // 这里传引用 不能复制两次
-        let kernel_stack = KernelStack::new(&pid_handle);
-        // 获取栈顶
-        let kernel_stack_top = kernel_stack.get_top();
-        // 构建TCB
-        let task_control_block = Self {
-            pid: pid_handle,
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: None,
-                    child: Vec::new(),
-                    exit_code: 0,
-                    // 自建结构体 用于统计进程运行时数据
-                    task_info_inner: TaskInfoInner::zero_init(),
-                    heap_bottom: user_sp,
-                    program_brk: user_sp,
-                    stride: 0,
-                    prio: 16,
-                    fd_table: vec![
-                        // 0 stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 stderr
-                        Some(Arc::new(Stdout)),
-                    ],
-                })
-            },
-        };
-
-        // 在User Space构建Trap Context
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-
-        task_control_block
-    }
-
-    /// exec系统调用
-    pub fn exec(&self, elf_data: &[u8]) {
-        // 实际上user_sp应该不会变
-        // 同时在构建的Memory Area中将数据拷贝过去
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-
-        let mut inner = self.inner_exclusive_access();
-        // 将from_elf生成的新的地址空间替换old
-        // old的生命周期结束，回收物理页
-        inner.memory_set = memory_set;
-        inner.trap_cx_ppn = trap_cx_ppn;
-
-        // 其他的都不变 只需要替换内存相关
-
-        let trap_cx = inner.get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            self.kernel_stack.get_top(),
-            trap_handler as usize,
-        );
-    }
-
-    /// fork系统调用
-    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
-        // 获取父进程PCB
-        let mut parent_inner = self.inner_exclusive_access();
-
-        // 复制user space
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-
-        // 分配PID
-        let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+    /// 在进程中创建一个线程, 分配tid并映射Trap上下文
+    /// Trap上下文由调用者初始化, 之后调用add_task开始执行
+    pub fn new(
+        process: &Arc<ProcessControlBlock>,
+        process_inner: &mut ProcessControlBlockInner,
+        alloc_ustack: bool,
+    ) -> Arc<Self> {
+        let tid = process_inner.alloc_tid();
+        let res = TaskUserRes::new(&mut process_inner.memory_set, tid, alloc_ustack);
+        let trap_cx_ppn = res.trap_cx_ppn(&process_inner.memory_set);
+        let kernel_stack = kstack_alloc();
         let kernel_stack_top = kernel_stack.get_top();
 
-        // 复制fd_table
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
-
-        // 使用ARC，实际的内存分配在堆上
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid: pid_handle,
+        let task = Arc::new(Self {
+            process: Arc::downgrade(process),
             kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    // 父亲引用为Self
-                    parent: Some(Arc::downgrade(self)),
-                    child: Vec::new(),
-                    exit_code: 0,
-                    task_info_inner: TaskInfoInner::zero_init(),
-                    // 与父进程完全保持一致
-                    heap_bottom: parent_inner.heap_bottom,
-                    program_brk: parent_inner.program_brk,
-                    stride: 0,
-                    prio: parent_inner.prio,
-                    fd_table: new_fd_table,
-                })
-            },
+            inner: SpinLock::new(TaskControlBlockInner {
+                res: Some(res),
+                trap_cx_ppn,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::Ready,
+                parked: false,
+                exit_code: None,
+                prio: 16,
+                stride: 0,
+                vruntime: 0,
+                level: 0,
+                slice_ticks: 0,
+                signal_mask: SignalFlags::empty(),
+                interrupted: false,
+                interruptible: false,
+            }),
         });
 
-        // add child
-        // 创建一个新引用
-        parent_inner.child.push(task_control_block.clone());
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-
-        // 这里与new不同之处在于，fork需要完整保存寄存器状态
-        // 此时复制出来的trap_cx所在ppn上已经存了之前的状态，但是需要修改kernel_stack为新分配的KernelStack
-        trap_cx.kernel_sp = kernel_stack_top;
-
-        task_control_block
-    }
-
-    /// 获取TaskInfoInner结构体
-    pub fn get_task_info_inner(&self) -> TaskInfoInner {
-        self.inner_exclusive_access().task_info_inner
-    }
-
-    /// change the location of the program break. return None if failed
-    pub fn change_program_brk(&self, size: i32) -> Option<usize> {
-        let mut inner = self.inner_exclusive_access();
-        let heap_bottom = inner.heap_bottom;
-        let old_break = inner.program_brk;
-        let new_brk = inner.program_brk as isize + size as isize;
-
-        // 如果新的位置小于heap底部
-        if new_brk < heap_bottom as isize {
-            return None;
-        }
-
-        let result = if size < 0 {
-            // 回收
-            inner
-                .memory_set
-                .shrink_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
-        } else {
-            // 增加
-            inner
-                .memory_set
-                .append_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
-        };
-
-        if result {
-            inner.program_brk = new_brk as usize;
-            Some(old_break)
+        if tid < process_inner.tasks.len() {
+            process_inner.tasks[tid] = Some(task.clone());
         } else {
-            None
+            process_inner.tasks.push(Some(task.clone()));
         }
+        task
+    }
+
+    /// 线程在进程内的编号
+    pub fn tid(&self) -> usize {
+        self.inner_exclusive_access().res.as_ref().unwrap().tid
     }
 
     /// 更新Stride
@@ -309,61 +232,6 @@ impl TaskControlBlock {
         let pass = BIG_STRIDE / inner.prio;
         inner.stride += pass;
     }
-
-    /// spwan=fork+exec
-    pub fn spwan(self: &Arc<Self>, elf_data: &[u8]) -> Arc<Self> {
-        let mut parent_inner = self.inner_exclusive_access();
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-        let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
-        let kernel_stack_top = kernel_stack.get_top();
-        // copy fd table
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
-
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid: pid_handle,
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    task_status: TaskStatus::Ready,
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_info_inner: TaskInfoInner::zero_init(),
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    child: Vec::new(),
-                    exit_code: 0,
-                    heap_bottom: user_sp,
-                    program_brk: user_sp,
-                    fd_table: new_fd_table,
-                    stride: 0,
-                    prio: 16,
-                })
-            },
-        });
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-        parent_inner.child.push(task_control_block.clone());
-        task_control_block
-    }
 }
 
 impl TaskControlBlockInner {
@@ -372,85 +240,117 @@ impl TaskControlBlockInner {
         self.trap_cx_ppn.get_mut()
     }
 
-    /// get token
-    pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
-    }
-
-    /// 获取进程状态
-    fn get_status(&self) -> TaskStatus {
-        self.task_status
+    /// 是否已经退出
+    pub fn is_exited(&self) -> bool {
+        self.exit_code.is_some()
     }
+}
 
-    /// 判断当前进程是不是僵尸进程
-    pub fn is_zombie(&self) -> bool {
-        self.get_status() == TaskStatus::Zombie
-    }
+/// 退出当前线程 执行下一个任务
+/// 与rCore一致, 主线程退出时整个进程退出
+pub fn exit_current_and_run_next(exit_code: i32) {
+    let group = current_task().unwrap().tid() == 0;
+    exit_current(exit_code, group);
+}
 
-    /// 更新当前系统调用计数器
-    pub fn update_syscall_times(&mut self, syscall_id: usize) {
-        self.task_info_inner.syscall_times[syscall_id] += 1;
-    }
+/// 结束当前进程的所有线程, 例如被信号终止
+/// 其他线程在返回用户态之前退出
+pub fn exit_group_and_run_next(exit_code: i32) {
+    exit_current(exit_code, true);
+}
 
-    /// 映射地址空间
-    pub fn mapping_address_space(
-        &mut self,
-        start_va: VirtAddr,
-        end_va: VirtAddr,
-        map_perm: MapPermission,
-    ) {
-        self.memory_set
-            .insert_framed_area(start_va, end_va, map_perm);
+fn exit_current(exit_code: i32, group: bool) {
+    // 当前线程仍然留在Processor中, 切换完成以后由idle控制流释放
+    // 在此之前内核栈不会被释放
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let mut process_inner = process.inner_exclusive_access();
+    // 最早的退出原因作为进程的exit_code
+    let start_exit = group && !process_inner.exiting;
+    if start_exit {
+        process_inner.exiting = true;
+        process_inner.exit_code = exit_code;
+        process_inner.stopped = false;
     }
 
-    /// 取消一块地址空间的映射
-    pub fn unmapping_address_space(&mut self, start_va: VirtAddr, end_va: VirtAddr) {
-        self.memory_set.munmap_area(start_va, end_va);
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.task_status = TaskStatus::Zombie;
+    task_inner.exit_code = Some(exit_code);
+    let res = task_inner.res.take();
+    drop(task_inner);
+    drop(task);
+    // 释放Trap上下文和用户栈, 之后不会再返回用户态
+    if let Some(res) = res {
+        res.dealloc(&mut process_inner.memory_set);
     }
 
-    /// 分配一个fd
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
+    // 最后一个退出的线程负责回收进程的资源
+    let last = process_inner
+        .tasks
+        .iter()
+        .flatten()
+        .all(|task| task.inner_exclusive_access().is_exited());
+    if !last {
+        // 被暂停或者正在等待的线程需要继续执行才能退出, SIGKILL不会被屏蔽, 因此会打断所有线程
+        let interrupted = if start_exit {
+            process_inner.interrupt_tasks(SignalFlags::SIGKILL)
         } else {
-            // 如果前面都有 那么在尾部增加一个
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
+            Vec::new()
+        };
+        drop(process_inner);
+        process.wait_thread.wake_all();
+        for task in interrupted {
+            wakeup_task(task);
+        }
+        if start_exit {
+            process.wait_continue.wake_all();
         }
+        let mut _unused = TaskContext::zero_init();
+        schedule(&mut _unused as *mut _);
+        return;
     }
-}
 
-/// 退出当前任务 执行下一个任务
-pub fn exit_current_and_run_next(exit_code: i32) {
-    // take当前任务
-    let task = take_current_task().unwrap();
-    // 获取TCB
-    let mut inner = task.inner_exclusive_access();
-    // 更改任务状态为僵尸进程
-    inner.task_status = TaskStatus::Zombie;
-
-    // 记录exit_code
-    inner.exit_code = exit_code;
-
-    {
-        // 将子进程挂在INITPROC下
-        let mut initproc_inner = INITPROC.inner_exclusive_access();
-        for child in inner.child.iter() {
-            // downgrade: 将INITPROC降级为Weak指针，而不增加引用
-            child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
-            initproc_inner.child.push(child.clone());
-        }
+    // 之后不能再向它发送信号
+    super::manager::remove_from_pid2process(process.get_pid());
+    if !process_inner.exiting {
+        process_inner.exit_code = exit_code;
     }
+    process_inner.is_zombie = true;
 
-    inner.child.clear();
     // 用于存放数据的物理页回收
     // 但不是很必要
-    inner.memory_set.recycle_data_pages();
-
-    drop(inner);
-    drop(task);
+    process_inner.memory_set.recycle_data_pages();
+
+    let children = core::mem::take(&mut process_inner.child);
+    let parent = process_inner.parent.clone();
+    // 关闭所有文件, 管道的对端不需要等到回收才能看到EOF
+    let fd_table = core::mem::take(&mut process_inner.fd_table);
+    drop(process_inner);
+    drop(fd_table);
+
+    // 通知父进程, 默认处理为忽略
+    if let Some(parent) = parent.and_then(|parent| parent.upgrade()) {
+        parent.send_signal(SignalFlags::SIGCHLD);
+        parent.wait_child.wake_all();
+    }
+    drop(process);
+
+    // 将子进程挂在INITPROC下
+    // 先释放当前进程的锁, 与waitpid中父进程到子进程的加锁顺序保持一致
+    let reparented = !children.is_empty();
+    let mut initproc_inner = INITPROC.inner_exclusive_access();
+    for child in children {
+        // downgrade: 将INITPROC降级为Weak指针，而不增加引用
+        child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+        initproc_inner.child.push(child);
+    }
+    drop(initproc_inner);
+    // 其中可能已经有僵尸进程
+    if reparented {
+        INITPROC.wait_child.wake_all();
+    }
 
-    // 该进程不会返回，因此不需要保存当前进程的上下文了
+    // 该线程不会返回，因此不需要保存当前线程的上下文了
     let mut _unused = TaskContext::zero_init();
     schedule(&mut _unused as *mut _);
 }
@@ -1,12 +1,19 @@
-//! system timer driver
+//! system timer driver, 以及内核定时器队列
 
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
+use crate::sync::SpinLock;
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskStatus};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
 const MSEC_PER_SEC: usize = 1000;
 const MICRO_PER_SEC: usize = 1_000_000;
+const NSEC_PER_SEC: usize = 1_000_000_000;
 
 /// get time ticks
 pub fn get_time() -> usize {
@@ -23,7 +30,119 @@ pub fn get_time_ms() -> usize {
     time::read() * MSEC_PER_SEC / CLOCK_FREQ
 }
 
+/// get time nano second
+pub fn get_time_ns() -> usize {
+    let ticks = time::read();
+    // 直接相乘会更早溢出
+    ticks / CLOCK_FREQ * NSEC_PER_SEC + ticks % CLOCK_FREQ * NSEC_PER_SEC / CLOCK_FREQ
+}
+
 /// set s-mode time for interrupt
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
+
+/// 定时器的编号, 用于取消定时器
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct TimerId(usize);
+
+/// 定时器到期时执行的回调
+type TimerCallback = Box<dyn FnOnce() + Send>;
+
+/// 内核定时器队列, 按到期时间排序
+struct TimerQueue {
+    /// (到期时间, 编号) -> 回调, 到期时间相同时按加入顺序执行
+    timers: BTreeMap<(usize, TimerId), TimerCallback>,
+    next_id: usize,
+}
+
+impl TimerQueue {
+    fn new() -> Self {
+        Self {
+            timers: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    fn add(&mut self, expire: usize, callback: TimerCallback) -> (usize, TimerId) {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+        self.timers.insert((expire, id), callback);
+        (expire, id)
+    }
+
+    /// 取出所有到期的定时器
+    fn pop_expired(&mut self, now: usize) -> Vec<TimerCallback> {
+        let mut expired = Vec::new();
+        while let Some(entry) = self.timers.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            expired.push(entry.remove());
+        }
+        expired
+    }
+}
+
+lazy_static! {
+    static ref TIMERS: SpinLock<TimerQueue> = SpinLock::new(TimerQueue::new());
+}
+
+/// 定时器的句柄, 可以在到期之前取消
+pub struct TimerHandle {
+    key: (usize, TimerId),
+}
+
+impl TimerHandle {
+    /// 取消定时器, 已经到期时返回false
+    pub fn cancel(self) -> bool {
+        TIMERS.lock().timers.remove(&self.key).is_some()
+    }
+}
+
+/// 在时钟计数到达expire时执行callback
+/// callback在时钟中断或者空闲核心上执行, 不能阻塞
+pub fn add_timer<F: FnOnce() + Send + 'static>(expire: usize, callback: F) -> TimerHandle {
+    let key = TIMERS.lock().add(expire, Box::new(callback));
+    TimerHandle { key }
+}
+
+/// 执行所有到期的定时器
+/// 在时钟中断和空闲的核心上调用
+pub fn check_timer() {
+    let expired = TIMERS.lock().pop_expired(get_time());
+    // 回调中可能会唤醒任务或者加入新的定时器
+    for callback in expired {
+        callback();
+    }
+}
+
+/// 当前任务睡眠到时钟计数到达expire, 被信号打断时提前返回false
+pub fn sleep_until(expire: usize) -> bool {
+    while get_time() < expire {
+        let task = current_task().unwrap();
+        // 先标记为Blocked再加入定时器, 在切换之前到期时相当于一次yield
+        let mut inner = task.inner_exclusive_access();
+        if inner.interrupted {
+            return false;
+        }
+        inner.task_status = TaskStatus::Blocked;
+        inner.interruptible = true;
+        drop(inner);
+        let timer = add_timer(expire, move || wakeup_task(task));
+        block_current_and_run_next();
+        // 被信号唤醒时定时器还没有到期
+        timer.cancel();
+    }
+    true
+}
+
+/// 当前任务睡眠us微秒, 被信号打断时提前返回false
+pub fn sleep_us(us: usize) -> bool {
+    sleep_until(get_time().saturating_add(us.saturating_mul(CLOCK_FREQ) / MICRO_PER_SEC))
+}
+
+/// 当前任务睡眠ms毫秒, 被信号打断时提前返回false
+pub fn sleep_ms(ms: usize) -> bool {
+    sleep_until(get_time().saturating_add(ms.saturating_mul(CLOCK_FREQ / MSEC_PER_SEC)))
+}
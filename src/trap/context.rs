@@ -16,6 +16,8 @@ pub struct TrapContext {
     pub kernel_sp: usize,
     /// entry of trap handler
     pub trap_handler: usize,
+    /// kernel tp, 即返回用户态时所在核心的hartid
+    pub kernel_tp: usize,
 }
 
 impl TrapContext {
@@ -42,6 +44,7 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            kernel_tp: 0,
         };
 
         cx.set_sp(sp);
@@ -3,9 +3,11 @@
 mod context;
 
 use crate::{
-    config::{TRAMPOLINE, TRAP_CONTEXT_BASE},
+    config::TRAMPOLINE,
+    fs::poll_stdin,
+    mm::VirtAddr,
     syscall::syscall,
-    task::current_task,
+    task::{current_process, current_task, page_fault_for_current_task},
 };
 use core::arch::global_asm;
 use riscv::register::{
@@ -15,10 +17,10 @@ use riscv::register::{
 
 use crate::{
     task::{
-        current_trap_cx, current_user_token, exit_current_and_run_next,
-        suspend_current_and_run_next,
+        current_trap_cx, current_trap_cx_user_va, current_user_token, handle_signals,
+        tick_current_and_run_next, SignalFlags,
     },
-    timer::set_next_trigger,
+    timer::{check_timer, set_next_trigger},
 };
 
 global_asm!(include_str!("trap.S"));
@@ -50,8 +52,10 @@ pub fn trap_handler() -> ! {
             // 当前应用的Trap上下文
             let mut cx = current_trap_cx();
             cx.sepc += 4;
-            let result =
-                syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14]]) as usize;
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            ) as usize;
             // 对于exec系统调用：旧的cx上下文已经被回收了，此时需要重新获取新的cx
             // 对于fork系统调用：父进程的x10在syscall中被修改，但是子进程的还未修改
             // 子进程的第一步入口一样在这个位置，因此需要修改子进程的返回值
@@ -59,27 +63,39 @@ pub fn trap_handler() -> ! {
             // 父进程相当于重复写入相同的值
             cx.x[10] = result as usize;
         }
+        Trap::Exception(Exception::StorePageFault)
+            if page_fault_for_current_task(VirtAddr::from(stval), true) =>
+        {
+            // 按需分配或者写时复制已经完成, 返回用户态重新执行写指令
+        }
+        Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionPageFault)
+            if page_fault_for_current_task(VirtAddr::from(stval), false) =>
+        {
+            // 按需分配已经完成, 返回用户态重新执行该指令
+        }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            println!("[Kernel] pid[{}] trap_handler: {:?} in application, bad addr = {:#x}, bad instruction = {:#x} kernel killed it!", current_task().unwrap().pid.0, scause.cause(), stval, current_trap_cx().sepc);
-            // page fault exit code
-            exit_current_and_run_next(-2);
+            debug!("[Kernel] pid[{}] trap_handler: {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, send SIGSEGV", current_process().pid.0, scause.cause(), stval, current_trap_cx().sepc);
+            // 没有处理函数时在返回用户态之前终止
+            current_task().unwrap().force_signal(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!(
-                "[Kernel] pid[{}] IllegalInstruction in application! Kernel killed it.",
-                current_task().unwrap().pid.0
+            debug!(
+                "[Kernel] pid[{}] IllegalInstruction in application, send SIGILL",
+                current_process().pid.0
             );
-            exit_current_and_run_next(-3);
+            current_task().unwrap().force_signal(SignalFlags::SIGILL);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
-            // stride不能在这里更新
-            suspend_current_and_run_next();
+            check_timer();
+            poll_stdin();
+            tick_current_and_run_next();
         }
         _ => {
             panic!(
@@ -116,11 +132,14 @@ fn set_user_trap_entry() {
 
 /// trap返回跳板函数
 pub fn trap_return() -> ! {
+    // 投递信号, 可能会修改TrapContext或者终止当前任务
+    handle_signals();
+
     // 设置为APP同一的跳板函数虚拟地址，即最高页
     set_user_trap_entry();
 
-    // 应用程序的TrapContext的VA也是固定的
-    let trap_cx_ptr = TRAP_CONTEXT_BASE;
+    // 每个线程的TrapContext位于不同的页面
+    let trap_cx_ptr = current_trap_cx_user_va();
     let user_satp = current_user_token();
 
     extern "C" {